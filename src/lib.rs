@@ -1,27 +1,51 @@
 #![no_std] // don't link the Rust standard library
+#![cfg_attr(test, no_main)]
 #![feature(abi_x86_interrupt)]
+#![feature(alloc_error_handler)]
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::test_runner)]
+#![reexport_test_harness_main = "test_main"]
 #![allow(clippy::missing_safety_doc)]
 
 extern crate alloc;
 
 use bootloader::BootInfo;
+use core::panic::PanicInfo;
 use low_level::{
-    allocator, gdt, interrupts,
-    memory::{self, PopFrameAllocator},
+    acpi::{self, Madt},
+    allocator, apic, gdt, interrupts,
+    memory::{self, KernelMemory, PopFrameAllocator, MEMORY},
+    mouse, ps2, scheduler, timer, vga_buffer,
 };
-use x86_64::VirtAddr;
+use userspace::locale::message;
+use x86_64::{instructions::port::Port, VirtAddr};
 
 pub mod low_level;
 pub mod userspace;
 pub fn init(boot_info: &'static BootInfo) {
-    initialize_gdt_and_interrupts();
-
     let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
 
-    let mut mapper = unsafe { memory::init(phys_mem_offset) };
-    let mut frame_allocator = unsafe { PopFrameAllocator::init(&boot_info.memory_map) };
+    let mapper = unsafe { memory::init(phys_mem_offset) };
+    let frame_allocator =
+        unsafe { PopFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    *MEMORY.lock() = Some(KernelMemory {
+        mapper,
+        frame_allocator,
+    });
+
+    // Without ACPI tables the APIC falls back to the standard PC configuration.
+    let _ = acpi::init(phys_mem_offset);
+    initialize_gdt_and_interrupts();
 
-    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    {
+        let mut memory = MEMORY.lock();
+        let memory = memory.as_mut().unwrap();
+        allocator::init_heap(&mut memory.mapper, &mut memory.frame_allocator)
+            .expect("heap initialization failed");
+    }
+    vga_buffer::init_scrollback();
+
+    scheduler::init();
 }
 
 pub fn hlt_loop() -> ! {
@@ -33,5 +57,72 @@ fn initialize_gdt_and_interrupts() {
     gdt::init();
     interrupts::init_idt();
     unsafe { interrupts::PICS.lock().initialize() };
+    timer::init();
+    // machines without an 8042 just have no keyboard or mouse
+    if ps2::init().is_ok_and(|controller| controller.second_port) && mouse::init().is_err() {
+        warn!(message("mouse.init_failed"));
+    }
+    let io_apic_config = acpi::tables()
+        .and_then(|tables| tables.madt.as_ref())
+        .and_then(Madt::io_apic_config)
+        .unwrap_or_default();
+    apic::init(&io_apic_config).expect("APIC initialization failed");
     x86_64::instructions::interrupts::enable();
 }
+
+pub trait Testable {
+    fn run(&self);
+}
+
+impl<T: Fn()> Testable for T {
+    fn run(&self) {
+        serial_print!("{}...\t", core::any::type_name::<T>());
+        self();
+        serial_println!("[ok]");
+    }
+}
+
+pub fn test_runner(tests: &[&dyn Testable]) {
+    serial_println!("Running {} tests", tests.len());
+    for test in tests {
+        test.run();
+    }
+    exit_qemu(QemuExitCode::Success);
+}
+
+pub fn test_panic_handler(info: &PanicInfo) -> ! {
+    serial_println!("[failed]\n");
+    serial_println!("Error: {}\n", info);
+    exit_qemu(QemuExitCode::Failed);
+    hlt_loop();
+}
+
+/// Exit codes written to QEMU's `isa-debug-exit` device. QEMU exits with `(code << 1) | 1`,
+/// which is why `test-success-exit-code` in Cargo.toml is 33.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failed = 0x11,
+}
+
+pub fn exit_qemu(exit_code: QemuExitCode) {
+    let mut port = Port::new(0xf4);
+    unsafe { port.write(exit_code as u32) };
+}
+
+#[cfg(test)]
+bootloader::entry_point!(test_kernel_main);
+
+#[cfg(test)]
+fn test_kernel_main(boot_info: &'static BootInfo) -> ! {
+    init(boot_info);
+    test_main();
+    hlt_loop();
+}
+
+#[cfg(test)]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    test_panic_handler(info)
+}
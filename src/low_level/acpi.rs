@@ -0,0 +1,203 @@
+//ACPI table discovery. init() finds the RSDP in the BIOS area, walks the RSDT or XSDT and
+//parses the tables the rest of the kernel needs. Everything is read through the bootloader's
+//mapping of physical memory, nothing is allocated, so this can run before the heap exists.
+use core::mem::size_of;
+use spin::Once;
+use x86_64::{PhysAddr, VirtAddr};
+
+pub use fadt::{AddressSpace, Fadt, GenericAddress};
+pub use hpet::Hpet;
+pub use madt::{IoApic, Madt, Processor};
+
+mod fadt;
+mod hpet;
+mod madt;
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+const EBDA_SEGMENT_POINTER: u64 = 0x40E;
+const EBDA_SEARCH_LENGTH: u64 = 1024;
+const BIOS_AREA_START: u64 = 0xE_0000;
+const BIOS_AREA_END: u64 = 0x10_0000;
+const RSDP_V1_LENGTH: usize = 20;
+const SDT_HEADER_LENGTH: usize = 36;
+
+static TABLES: Once<AcpiTables> = Once::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcpiError {
+    RsdpNotFound,
+    InvalidChecksum([u8; 4]),
+    InvalidLength([u8; 4]),
+}
+
+/// The tables found through the RSDP. A table is `None` if the firmware does not provide
+/// it or it failed to validate.
+pub struct AcpiTables {
+    pub revision: u8,
+    pub physical_memory_offset: VirtAddr,
+    pub madt: Option<Madt>,
+    pub fadt: Option<Fadt>,
+    pub hpet: Option<Hpet>,
+}
+
+/// A system description table with a valid checksum. `bytes` covers the whole table,
+/// including the 36 byte header.
+#[derive(Debug, Clone, Copy)]
+pub struct Sdt {
+    pub signature: [u8; 4],
+    pub revision: u8,
+    pub bytes: &'static [u8],
+}
+
+impl Sdt {
+    /// Validates the table at `address`.
+    ///
+    /// # Safety
+    /// All of physical memory has to be mapped at `physical_memory_offset`.
+    pub unsafe fn at(
+        physical_memory_offset: VirtAddr,
+        address: PhysAddr,
+    ) -> Result<Sdt, AcpiError> {
+        let header = physical_bytes(physical_memory_offset, address, SDT_HEADER_LENGTH);
+        let signature = read::<[u8; 4]>(header, 0).unwrap();
+        let length = read::<u32>(header, 4).unwrap() as usize;
+        if length < SDT_HEADER_LENGTH {
+            return Err(AcpiError::InvalidLength(signature));
+        }
+        let bytes = physical_bytes(physical_memory_offset, address, length);
+        if !checksum_is_valid(bytes) {
+            return Err(AcpiError::InvalidChecksum(signature));
+        }
+        Ok(Sdt {
+            signature,
+            revision: header[8],
+            bytes,
+        })
+    }
+
+    /// The part of the table after the header.
+    pub fn body(&self) -> &'static [u8] {
+        &self.bytes[SDT_HEADER_LENGTH..]
+    }
+}
+
+/// The tables parsed by `init`, if it succeeded.
+pub fn tables() -> Option<&'static AcpiTables> {
+    TABLES.get()
+}
+
+/// Finds and parses the ACPI tables. `physical_memory_offset` is where the bootloader
+/// mapped physical memory, the same offset `memory::init` takes.
+pub fn init(physical_memory_offset: VirtAddr) -> Result<&'static AcpiTables, AcpiError> {
+    if let Some(tables) = TABLES.get() {
+        return Ok(tables);
+    }
+    let tables = unsafe { parse_tables(physical_memory_offset)? };
+    Ok(TABLES.call_once(|| tables))
+}
+
+unsafe fn parse_tables(physical_memory_offset: VirtAddr) -> Result<AcpiTables, AcpiError> {
+    let rsdp = find_rsdp(physical_memory_offset).ok_or(AcpiError::RsdpNotFound)?;
+    let revision = rsdp[15];
+    let xsdt_address = read::<u64>(rsdp, 24).filter(|&address| revision >= 2 && address != 0);
+    let (root, entry_size) = match xsdt_address {
+        Some(address) => (Sdt::at(physical_memory_offset, PhysAddr::new(address))?, 8),
+        None => {
+            let address = u64::from(read::<u32>(rsdp, 16).unwrap());
+            (Sdt::at(physical_memory_offset, PhysAddr::new(address))?, 4)
+        }
+    };
+
+    let mut tables = AcpiTables {
+        revision,
+        physical_memory_offset,
+        madt: None,
+        fadt: None,
+        hpet: None,
+    };
+    for entry in root.body().chunks_exact(entry_size) {
+        let address = match entry_size {
+            8 => read::<u64>(entry, 0).unwrap(),
+            _ => u64::from(read::<u32>(entry, 0).unwrap()),
+        };
+        let Ok(table) = Sdt::at(physical_memory_offset, PhysAddr::new(address)) else {
+            continue;
+        };
+        match &table.signature {
+            b"APIC" => tables.madt = Madt::parse(&table),
+            b"FACP" => tables.fadt = Fadt::parse(&table),
+            b"HPET" => tables.hpet = Hpet::parse(&table),
+            _ => {}
+        }
+    }
+    Ok(tables)
+}
+
+/// Looks for the RSDP in the first KiB of the EBDA, then in the BIOS read-only area.
+unsafe fn find_rsdp(physical_memory_offset: VirtAddr) -> Option<&'static [u8]> {
+    let ebda_segment = (physical_memory_offset + EBDA_SEGMENT_POINTER)
+        .as_ptr::<u16>()
+        .read_unaligned();
+    let ebda_start = u64::from(ebda_segment) << 4;
+    let ebda = (ebda_start != 0).then_some(ebda_start..ebda_start + EBDA_SEARCH_LENGTH);
+    ebda.into_iter()
+        .chain(core::iter::once(BIOS_AREA_START..BIOS_AREA_END))
+        .flat_map(|area| area.step_by(16))
+        .find_map(|address| {
+            let candidate = physical_bytes(
+                physical_memory_offset,
+                PhysAddr::new(address),
+                RSDP_V1_LENGTH,
+            );
+            if candidate[..8] != RSDP_SIGNATURE[..] || !checksum_is_valid(candidate) {
+                return None;
+            }
+            if candidate[15] < 2 {
+                return Some(candidate);
+            }
+            let length = read::<u32>(
+                physical_bytes(physical_memory_offset, PhysAddr::new(address), 24),
+                20,
+            )? as usize;
+            let rsdp = physical_bytes(physical_memory_offset, PhysAddr::new(address), length);
+            (length >= 32 && checksum_is_valid(rsdp)).then_some(rsdp)
+        })
+}
+
+unsafe fn physical_bytes(
+    physical_memory_offset: VirtAddr,
+    address: PhysAddr,
+    length: usize,
+) -> &'static [u8] {
+    let start = physical_memory_offset + address.as_u64();
+    core::slice::from_raw_parts(start.as_ptr::<u8>(), length)
+}
+
+/// ACPI structures are valid if all their bytes add up to zero.
+fn checksum_is_valid(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0
+}
+
+/// Reads a little endian value of plain integers at `offset`, if `bytes` is long enough.
+fn read<T: Copy>(bytes: &[u8], offset: usize) -> Option<T> {
+    let end = offset.checked_add(size_of::<T>())?;
+    if end > bytes.len() {
+        return None;
+    }
+    Some(unsafe { bytes.as_ptr().add(offset).cast::<T>().read_unaligned() })
+}
+
+#[test_case]
+fn checksum_covers_every_byte() {
+    assert!(checksum_is_valid(&[0x10, 0xF0]));
+    assert!(!checksum_is_valid(&[0x10, 0xF0, 0x01]));
+    assert!(checksum_is_valid(&[]));
+}
+
+#[test_case]
+fn reads_stay_in_bounds() {
+    let bytes = [0x78, 0x56, 0x34, 0x12, 0xFF];
+    assert_eq!(read::<u32>(&bytes, 0), Some(0x1234_5678));
+    assert_eq!(read::<u32>(&bytes, 2), None);
+    assert_eq!(read::<u8>(&bytes, 4), Some(0xFF));
+}
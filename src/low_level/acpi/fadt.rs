@@ -0,0 +1,97 @@
+//The Fixed ACPI Description Table, which has the power management ports and the reset register.
+use x86_64::PhysAddr;
+
+use super::{read, Sdt};
+
+const FLAG_RESET_REGISTER_SUPPORTED: u32 = 1 << 10;
+
+/// Where a register lives, as ACPI describes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GenericAddress {
+    pub address_space: AddressSpace,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressSpace {
+    SystemMemory,
+    SystemIo,
+    PciConfiguration,
+    Other(u8),
+}
+
+impl GenericAddress {
+    pub(super) fn parse(bytes: &[u8], offset: usize) -> Option<GenericAddress> {
+        let address_space = match read::<u8>(bytes, offset)? {
+            0 => AddressSpace::SystemMemory,
+            1 => AddressSpace::SystemIo,
+            2 => AddressSpace::PciConfiguration,
+            other => AddressSpace::Other(other),
+        };
+        Some(GenericAddress {
+            address_space,
+            bit_width: read::<u8>(bytes, offset + 1)?,
+            bit_offset: read::<u8>(bytes, offset + 2)?,
+            access_size: read::<u8>(bytes, offset + 3)?,
+            address: read::<u64>(bytes, offset + 4)?,
+        })
+    }
+}
+
+/// The parsed FADT. Port fields are `None` when the firmware leaves them at zero.
+#[derive(Debug, Clone, Copy)]
+pub struct Fadt {
+    pub dsdt: PhysAddr,
+    pub sci_interrupt: u16,
+    /// Writing `acpi_enable` to this port switches the chipset from legacy to ACPI mode.
+    pub smi_command_port: Option<u16>,
+    pub acpi_enable: u8,
+    pub acpi_disable: u8,
+    pub pm1a_event_block: Option<u16>,
+    pub pm1b_event_block: Option<u16>,
+    pub pm1a_control_block: Option<u16>,
+    pub pm1b_control_block: Option<u16>,
+    pub pm_timer_block: Option<u16>,
+    pub pm1_event_length: u8,
+    pub pm1_control_length: u8,
+    pub pm_timer_length: u8,
+    /// Writing `reset_value` to this register resets the machine.
+    pub reset_register: Option<GenericAddress>,
+    pub reset_value: u8,
+}
+
+impl Fadt {
+    pub fn parse(table: &Sdt) -> Option<Fadt> {
+        let bytes = table.bytes;
+        let port = |offset| {
+            read::<u32>(bytes, offset)
+                .filter(|&port| port != 0)
+                .and_then(|port| u16::try_from(port).ok())
+        };
+        let flags = read::<u32>(bytes, 112).unwrap_or(0);
+        let reset_register = GenericAddress::parse(bytes, 116)
+            .filter(|_| flags & FLAG_RESET_REGISTER_SUPPORTED != 0);
+        let x_dsdt = read::<u64>(bytes, 140).filter(|&address| address != 0);
+        let dsdt = x_dsdt.unwrap_or(read::<u32>(bytes, 40)?.into());
+        Some(Fadt {
+            dsdt: PhysAddr::new(dsdt),
+            sci_interrupt: read::<u16>(bytes, 46)?,
+            smi_command_port: port(48),
+            acpi_enable: read::<u8>(bytes, 52)?,
+            acpi_disable: read::<u8>(bytes, 53)?,
+            pm1a_event_block: port(56),
+            pm1b_event_block: port(60),
+            pm1a_control_block: port(64),
+            pm1b_control_block: port(68),
+            pm_timer_block: port(76),
+            pm1_event_length: read::<u8>(bytes, 88)?,
+            pm1_control_length: read::<u8>(bytes, 89)?,
+            pm_timer_length: read::<u8>(bytes, 91)?,
+            reset_register,
+            reset_value: read::<u8>(bytes, 128).unwrap_or(0),
+        })
+    }
+}
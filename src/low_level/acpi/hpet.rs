@@ -0,0 +1,33 @@
+//The High Precision Event Timer table.
+use super::{fadt::GenericAddress, read, Sdt};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Hpet {
+    pub hardware_revision: u8,
+    pub comparator_count: u8,
+    pub counter_is_64_bit: bool,
+    pub legacy_replacement_capable: bool,
+    pub pci_vendor_id: u16,
+    /// The memory mapped registers of the timer block.
+    pub base_address: GenericAddress,
+    pub number: u8,
+    /// The smallest period, in main counter ticks, that periodic mode can be used with.
+    pub minimum_tick: u16,
+}
+
+impl Hpet {
+    pub fn parse(table: &Sdt) -> Option<Hpet> {
+        let bytes = table.bytes;
+        let block_id = read::<u32>(bytes, 36)?;
+        Some(Hpet {
+            hardware_revision: block_id as u8,
+            comparator_count: ((block_id >> 8) & 0x1F) as u8 + 1,
+            counter_is_64_bit: block_id & (1 << 13) != 0,
+            legacy_replacement_capable: block_id & (1 << 15) != 0,
+            pci_vendor_id: (block_id >> 16) as u16,
+            base_address: GenericAddress::parse(bytes, 40)?,
+            number: read::<u8>(bytes, 52)?,
+            minimum_tick: read::<u16>(bytes, 53)?,
+        })
+    }
+}
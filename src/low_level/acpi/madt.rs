@@ -0,0 +1,180 @@
+//The Multiple APIC Description Table: which processors and I/O APICs there are, and how the
+//ISA IRQs are wired to the I/O APIC inputs.
+use x86_64::PhysAddr;
+
+use super::{read, Sdt};
+use crate::low_level::apic::{InterruptOverride, IoApicConfig};
+
+pub const MAX_PROCESSORS: usize = 64;
+pub const MAX_IO_APICS: usize = 8;
+pub const MAX_INTERRUPT_OVERRIDES: usize = 16;
+
+const ENTRY_LOCAL_APIC: u8 = 0;
+const ENTRY_IO_APIC: u8 = 1;
+const ENTRY_INTERRUPT_OVERRIDE: u8 = 2;
+const ENTRY_LOCAL_APIC_ADDRESS: u8 = 5;
+const ENTRY_LOCAL_X2APIC: u8 = 9;
+
+const PROCESSOR_ENABLED: u32 = 1 << 0;
+const PROCESSOR_ONLINE_CAPABLE: u32 = 1 << 1;
+const FLAG_PCAT_COMPATIBLE: u32 = 1 << 0;
+const POLARITY_ACTIVE_LOW: u16 = 0b11;
+const TRIGGER_LEVEL: u16 = 0b11 << 2;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Processor {
+    pub acpi_id: u32,
+    pub apic_id: u32,
+    /// Whether the processor is usable now. Processors that are not can still be
+    /// `online_capable`, meaning firmware may enable them later.
+    pub enabled: bool,
+    pub online_capable: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IoApic {
+    pub id: u8,
+    pub address: PhysAddr,
+    pub gsi_base: u32,
+}
+
+/// The parsed MADT. Entries past the fixed capacities are ignored.
+#[derive(Debug, Clone)]
+pub struct Madt {
+    pub local_apic_address: PhysAddr,
+    /// Whether the machine also has the two 8259 PICs, which then have to be masked.
+    pub has_legacy_pics: bool,
+    processors: [Option<Processor>; MAX_PROCESSORS],
+    io_apics: [Option<IoApic>; MAX_IO_APICS],
+    interrupt_overrides: [InterruptOverride; MAX_INTERRUPT_OVERRIDES],
+    interrupt_override_count: usize,
+}
+
+impl Madt {
+    pub fn parse(table: &Sdt) -> Option<Madt> {
+        Self::parse_body(table.body())
+    }
+
+    fn parse_body(body: &[u8]) -> Option<Madt> {
+        let mut madt = Madt {
+            local_apic_address: PhysAddr::new(read::<u32>(body, 0)?.into()),
+            has_legacy_pics: read::<u32>(body, 4)? & FLAG_PCAT_COMPATIBLE != 0,
+            processors: [None; MAX_PROCESSORS],
+            io_apics: [None; MAX_IO_APICS],
+            interrupt_overrides: [InterruptOverride::default(); MAX_INTERRUPT_OVERRIDES],
+            interrupt_override_count: 0,
+        };
+
+        let mut entries = &body[8..];
+        while let (Some(entry_type), Some(length)) =
+            (read::<u8>(entries, 0), read::<u8>(entries, 1))
+        {
+            let length = usize::from(length);
+            if length < 2 || length > entries.len() {
+                break;
+            }
+            let (entry, rest) = entries.split_at(length);
+            entries = rest;
+            match entry_type {
+                ENTRY_LOCAL_APIC => {
+                    let flags = read::<u32>(entry, 4)?;
+                    madt.add_processor(Processor {
+                        acpi_id: read::<u8>(entry, 2)?.into(),
+                        apic_id: read::<u8>(entry, 3)?.into(),
+                        enabled: flags & PROCESSOR_ENABLED != 0,
+                        online_capable: flags & PROCESSOR_ONLINE_CAPABLE != 0,
+                    });
+                }
+                ENTRY_LOCAL_X2APIC => {
+                    let flags = read::<u32>(entry, 8)?;
+                    madt.add_processor(Processor {
+                        acpi_id: read::<u32>(entry, 12)?,
+                        apic_id: read::<u32>(entry, 4)?,
+                        enabled: flags & PROCESSOR_ENABLED != 0,
+                        online_capable: flags & PROCESSOR_ONLINE_CAPABLE != 0,
+                    });
+                }
+                ENTRY_IO_APIC => {
+                    let io_apic = IoApic {
+                        id: read::<u8>(entry, 2)?,
+                        address: PhysAddr::new(read::<u32>(entry, 4)?.into()),
+                        gsi_base: read::<u32>(entry, 8)?,
+                    };
+                    if let Some(slot) = madt.io_apics.iter_mut().find(|slot| slot.is_none()) {
+                        *slot = Some(io_apic);
+                    }
+                }
+                ENTRY_INTERRUPT_OVERRIDE => {
+                    let flags = read::<u16>(entry, 8)?;
+                    if madt.interrupt_override_count < MAX_INTERRUPT_OVERRIDES {
+                        madt.interrupt_overrides[madt.interrupt_override_count] =
+                            InterruptOverride {
+                                irq: read::<u8>(entry, 3)?,
+                                gsi: read::<u32>(entry, 4)?,
+                                active_low: flags & POLARITY_ACTIVE_LOW == POLARITY_ACTIVE_LOW,
+                                level_triggered: flags & TRIGGER_LEVEL == TRIGGER_LEVEL,
+                            };
+                        madt.interrupt_override_count += 1;
+                    }
+                }
+                ENTRY_LOCAL_APIC_ADDRESS => {
+                    madt.local_apic_address = PhysAddr::new(read::<u64>(entry, 4)?);
+                }
+                _ => {}
+            }
+        }
+        Some(madt)
+    }
+
+    fn add_processor(&mut self, processor: Processor) {
+        if let Some(slot) = self.processors.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(processor);
+        }
+    }
+
+    pub fn processors(&self) -> impl Iterator<Item = &Processor> {
+        self.processors.iter().flatten()
+    }
+
+    pub fn io_apics(&self) -> impl Iterator<Item = &IoApic> {
+        self.io_apics.iter().flatten()
+    }
+
+    pub fn interrupt_overrides(&self) -> &[InterruptOverride] {
+        &self.interrupt_overrides[..self.interrupt_override_count]
+    }
+
+    /// The configuration for the I/O APIC that handles the ISA IRQs, the one whose inputs
+    /// start at GSI 0.
+    pub fn io_apic_config(&self) -> Option<IoApicConfig<'_>> {
+        let io_apic = self.io_apics().find(|io_apic| io_apic.gsi_base == 0)?;
+        Some(IoApicConfig {
+            address: io_apic.address,
+            gsi_base: io_apic.gsi_base,
+            overrides: self.interrupt_overrides(),
+        })
+    }
+}
+
+#[test_case]
+fn parses_processors_io_apics_and_overrides() {
+    #[rustfmt::skip]
+    let body = [
+        0x00, 0x00, 0xE0, 0xFE, 0x01, 0x00, 0x00, 0x00, // local APIC address, PC-AT compatible
+        0, 8, 0, 0, 0x01, 0x00, 0x00, 0x00, // processor 0, enabled
+        0, 8, 1, 1, 0x02, 0x00, 0x00, 0x00, // processor 1, online capable
+        1, 12, 2, 0, 0x00, 0x00, 0xC0, 0xFE, 0, 0, 0, 0, // I/O APIC 2 at 0xFEC00000
+        2, 10, 0, 0, 2, 0, 0, 0, 0x00, 0x00, // IRQ 0 -> GSI 2
+        2, 10, 0, 9, 9, 0, 0, 0, 0x0F, 0x00, // IRQ 9, active low, level triggered
+    ];
+    let madt = Madt::parse_body(&body).unwrap();
+    assert_eq!(madt.local_apic_address.as_u64(), 0xFEE0_0000);
+    assert!(madt.has_legacy_pics);
+    assert_eq!(madt.processors().count(), 2);
+    assert!(madt.processors().nth(1).unwrap().online_capable);
+    let config = madt.io_apic_config().unwrap();
+    assert_eq!(config.address.as_u64(), 0xFEC0_0000);
+    assert_eq!(config.overrides.len(), 2);
+    assert_eq!(config.overrides[0].gsi, 2);
+    assert!(config.overrides[1].active_low && config.overrides[1].level_triggered);
+}
@@ -1,24 +1,177 @@
-use linked_list_allocator::LockedHeap;
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    fmt,
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use linked_list_allocator::Heap;
 use x86_64::{
     structures::paging::{
-        mapper::MapToError, page::PageRangeInclusive, FrameAllocator, Mapper, Page, PageTableFlags,
-        Size4KiB,
+        mapper::MapToError, page::PageRangeInclusive, FrameAllocator, FrameDeallocator, Mapper,
+        Page, PageSize, PageTableFlags, Size4KiB,
     },
     VirtAddr,
 };
 
+use crate::{
+    low_level::{memory::MEMORY, sync::IrqSafeMutex},
+    println,
+};
+use fixed_size_block::FixedSizeBlockAllocator;
+pub use fixed_size_block::{SizeClassStats, BLOCK_SIZES};
+#[cfg(feature = "heap-debug")]
+pub use leak_tracker::{dump_leaks, leak_checkpoint};
+
+mod fixed_size_block;
+#[cfg(feature = "heap-debug")]
+mod leak_tracker;
+
 pub const HEAP_START: usize = 0x_4444_4444_0000;
-pub const HEAP_SIZE: usize = 100 * 1024; // 100 KiB
+pub const HEAP_SIZE: usize = 100 * 1024; // 100 KiB, mapped up front
+pub const HEAP_MAX_SIZE: usize = 64 * 1024 * 1024; // 64 MiB, the heap never grows past this
+const HEAP_GROW_STEP: usize = 64 * 1024; // 64 KiB, smallest amount mapped per growth
 
 #[global_allocator]
-static ALLOCATOR: LockedHeap = LockedHeap::empty();
+static ALLOCATOR: GrowableHeap = GrowableHeap::empty();
+
+/// A slab allocator whose fallback heap maps more pages after its current top whenever an
+/// allocation does not fit, until it reaches `HEAP_MAX_SIZE`.
+pub struct GrowableHeap {
+    /// Interrupts stay off while it is held, so a thread can't be preempted while holding it
+    /// and keep everyone else spinning on it.
+    allocator: IrqSafeMutex<FixedSizeBlockAllocator>,
+    used: AtomicUsize,
+    peak: AtomicUsize,
+    allocations: AtomicUsize,
+    total_allocations: AtomicUsize,
+    failed_allocations: AtomicUsize,
+}
+
+impl GrowableHeap {
+    pub const fn empty() -> Self {
+        GrowableHeap {
+            allocator: IrqSafeMutex::new(FixedSizeBlockAllocator::new()),
+            used: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            allocations: AtomicUsize::new(0),
+            total_allocations: AtomicUsize::new(0),
+            failed_allocations: AtomicUsize::new(0),
+        }
+    }
+
+    fn allocate(&self, layout: Layout) -> Option<NonNull<u8>> {
+        let mut allocator = self.allocator.lock();
+        if let Some(allocation) = allocator.allocate(layout) {
+            return Some(allocation);
+        }
+        grow_heap(allocator.fallback_heap(), layout).ok()?;
+        allocator.allocate(layout)
+    }
+}
+
+unsafe impl GlobalAlloc for GrowableHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let Some(allocation) = self.allocate(layout) else {
+            self.failed_allocations.fetch_add(1, Ordering::Relaxed);
+            return ptr::null_mut();
+        };
+        let used = self.used.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        self.peak.fetch_max(used, Ordering::Relaxed);
+        self.allocations.fetch_add(1, Ordering::Relaxed);
+        self.total_allocations.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "heap-debug")]
+        leak_tracker::record_allocation(allocation.as_ptr() as usize, layout.size());
+        allocation.as_ptr()
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.allocator
+            .lock()
+            .deallocate(NonNull::new_unchecked(ptr), layout);
+        self.used.fetch_sub(layout.size(), Ordering::Relaxed);
+        self.allocations.fetch_sub(1, Ordering::Relaxed);
+        #[cfg(feature = "heap-debug")]
+        leak_tracker::record_deallocation(ptr as usize);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    /// Bytes currently mapped for the heap.
+    pub size: usize,
+    /// Bytes handed out to live allocations, as requested by their layouts.
+    pub used: usize,
+    /// Bytes that can still be handed out without growing the heap.
+    pub free: usize,
+    /// Highest value `used` has reached since boot.
+    pub peak: usize,
+    /// Number of live allocations.
+    pub allocations: usize,
+    /// Number of allocations made since boot.
+    pub total_allocations: usize,
+    /// Number of allocations that failed even after trying to grow the heap.
+    pub failed_allocations: usize,
+}
+
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} bytes used, {} bytes free, {} of {} bytes mapped, peak {} bytes, \
+            {} live of {} allocations, {} failed",
+            self.used,
+            self.free,
+            self.size,
+            HEAP_MAX_SIZE,
+            self.peak,
+            self.allocations,
+            self.total_allocations,
+            self.failed_allocations
+        )
+    }
+}
+
+pub fn heap_stats() -> HeapStats {
+    let mut allocator = ALLOCATOR.allocator.lock();
+    let cached: usize = allocator
+        .size_classes()
+        .iter()
+        .zip(BLOCK_SIZES)
+        .map(|(size_class, block_size)| size_class.free_blocks * block_size)
+        .sum();
+    let heap = allocator.fallback_heap();
+    HeapStats {
+        size: heap.size(),
+        used: ALLOCATOR.used.load(Ordering::Relaxed),
+        free: heap.free() + cached,
+        peak: ALLOCATOR.peak.load(Ordering::Relaxed),
+        allocations: ALLOCATOR.allocations.load(Ordering::Relaxed),
+        total_allocations: ALLOCATOR.total_allocations.load(Ordering::Relaxed),
+        failed_allocations: ALLOCATOR.failed_allocations.load(Ordering::Relaxed),
+    }
+}
+
+/// Counters for every size class in `BLOCK_SIZES` order, and for the large allocations
+/// that went straight to the fallback heap.
+pub fn size_class_stats() -> ([SizeClassStats; BLOCK_SIZES.len()], SizeClassStats) {
+    let allocator = ALLOCATOR.allocator.lock();
+    (*allocator.size_classes(), *allocator.large_allocations())
+}
 
 pub fn init_heap(
     mapper: &mut impl Mapper<Size4KiB>,
     frame_allocator: &mut impl FrameAllocator<Size4KiB>,
 ) -> Result<(), MapToError<Size4KiB>> {
-    let page_range = get_page_range();
-
+    let page_range = get_page_range(HEAP_START, HEAP_SIZE);
+    map_heap_pages(page_range, mapper, frame_allocator)?;
+    create_empty_heap();
+    Ok(())
+}
+fn map_heap_pages(
+    page_range: PageRangeInclusive,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size4KiB>> {
     for page in page_range {
         let frame = frame_allocator
             .allocate_frame()
@@ -26,12 +179,11 @@ pub fn init_heap(
         let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
         unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
     }
-    create_empty_heap();
     Ok(())
 }
-fn get_page_range() -> PageRangeInclusive {
-    let heap_start = VirtAddr::new(HEAP_START as u64);
-    let heap_end = heap_start + HEAP_SIZE - 1u64;
+fn get_page_range(start: usize, size: usize) -> PageRangeInclusive {
+    let heap_start = VirtAddr::new(start as u64);
+    let heap_end = heap_start + size - 1u64;
     let heap_start_page = Page::containing_address(heap_start);
     let heap_end_page = Page::containing_address(heap_end);
     Page::range_inclusive(heap_start_page, heap_end_page)
@@ -39,6 +191,48 @@ fn get_page_range() -> PageRangeInclusive {
 fn create_empty_heap() {
     unsafe {
         let raw_heap_start = HEAP_START as *mut u8;
-        ALLOCATOR.lock().init(raw_heap_start, HEAP_SIZE);
+        ALLOCATOR.allocator.lock().init(raw_heap_start, HEAP_SIZE);
     }
 }
+
+/// Maps enough pages after the top of the heap to fit `layout` and hands them to the heap.
+/// Needs `memory::MEMORY` to be set up, so the heap can only grow after boot has finished
+/// initializing memory.
+fn grow_heap(heap: &mut Heap, layout: Layout) -> Result<(), MapToError<Size4KiB>> {
+    let needed = (layout.size() + layout.align()).next_multiple_of(Size4KiB::SIZE as usize);
+    let grow_by = needed.max(HEAP_GROW_STEP);
+    if heap.size() + grow_by > HEAP_MAX_SIZE {
+        return Err(MapToError::FrameAllocationFailed);
+    }
+
+    let mut memory = MEMORY.lock();
+    let memory = memory.as_mut().ok_or(MapToError::FrameAllocationFailed)?;
+    // one page at a time, so a failure part-way leaves nothing mapped above the heap's top
+    // and the next growth starts where this one stopped
+    for page in get_page_range(heap.top() as usize, grow_by) {
+        let frame = memory
+            .frame_allocator
+            .allocate_frame()
+            .ok_or(MapToError::FrameAllocationFailed)?;
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        match unsafe {
+            memory
+                .mapper
+                .map_to(page, frame, flags, &mut memory.frame_allocator)
+        } {
+            Ok(flush) => flush.flush(),
+            Err(error) => {
+                unsafe { memory.frame_allocator.deallocate_frame(frame) };
+                return Err(error);
+            }
+        }
+        unsafe { heap.extend(Size4KiB::SIZE as usize) };
+    }
+    Ok(())
+}
+
+#[alloc_error_handler]
+fn alloc_error_handler(layout: Layout) -> ! {
+    println!("Heap: {}", heap_stats());
+    panic!("allocation error: {:?}", layout)
+}
@@ -0,0 +1,125 @@
+use core::{alloc::Layout, mem, ptr::NonNull};
+use linked_list_allocator::Heap;
+
+/// The block sizes to use, smallest first. Every size is also used as the block alignment,
+/// so they all have to be powers of two. Anything bigger goes to the fallback heap.
+pub const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+
+struct ListNode {
+    next: Option<&'static mut ListNode>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SizeClassStats {
+    /// Number of allocations served by this size class since boot.
+    pub allocations: usize,
+    /// Number of blocks given back to this size class since boot.
+    pub deallocations: usize,
+    /// Number of blocks sitting in the free list, ready to be reused.
+    pub free_blocks: usize,
+}
+
+impl SizeClassStats {
+    pub fn live(&self) -> usize {
+        self.allocations - self.deallocations
+    }
+}
+
+/// Slab allocator with one free list per entry of `BLOCK_SIZES`, falling back to a
+/// linked list heap for large allocations and for carving out new blocks.
+pub struct FixedSizeBlockAllocator {
+    list_heads: [Option<&'static mut ListNode>; BLOCK_SIZES.len()],
+    size_classes: [SizeClassStats; BLOCK_SIZES.len()],
+    large: SizeClassStats,
+    fallback_allocator: Heap,
+}
+
+impl FixedSizeBlockAllocator {
+    pub const fn new() -> Self {
+        const EMPTY: Option<&'static mut ListNode> = None;
+        const NO_STATS: SizeClassStats = SizeClassStats {
+            allocations: 0,
+            deallocations: 0,
+            free_blocks: 0,
+        };
+        FixedSizeBlockAllocator {
+            list_heads: [EMPTY; BLOCK_SIZES.len()],
+            size_classes: [NO_STATS; BLOCK_SIZES.len()],
+            large: NO_STATS,
+            fallback_allocator: Heap::empty(),
+        }
+    }
+
+    /// # Safety
+    /// The given memory range must be unused and mapped, and this may only be called once.
+    pub unsafe fn init(&mut self, heap_start: *mut u8, heap_size: usize) {
+        self.fallback_allocator.init(heap_start, heap_size);
+    }
+
+    pub fn fallback_heap(&mut self) -> &mut Heap {
+        &mut self.fallback_allocator
+    }
+
+    /// Per size class counters, in the same order as `BLOCK_SIZES`.
+    pub fn size_classes(&self) -> &[SizeClassStats; BLOCK_SIZES.len()] {
+        &self.size_classes
+    }
+
+    /// Counters for allocations too large for any size class.
+    pub fn large_allocations(&self) -> &SizeClassStats {
+        &self.large
+    }
+
+    pub fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        let Some(index) = list_index(&layout) else {
+            let allocation = self.fallback_allocator.allocate_first_fit(layout).ok()?;
+            self.large.allocations += 1;
+            return Some(allocation);
+        };
+        let allocation = match self.list_heads[index].take() {
+            Some(node) => {
+                self.list_heads[index] = node.next.take();
+                self.size_classes[index].free_blocks -= 1;
+                NonNull::from(node).cast()
+            }
+            None => {
+                // no block exists in list => allocate new block
+                let block_size = BLOCK_SIZES[index];
+                // only works if all block sizes are a power of 2
+                let block_align = block_size;
+                let layout = Layout::from_size_align(block_size, block_align).unwrap();
+                self.fallback_allocator.allocate_first_fit(layout).ok()?
+            }
+        };
+        self.size_classes[index].allocations += 1;
+        Some(allocation)
+    }
+
+    /// # Safety
+    /// `ptr` must have been returned by `allocate` with the same `layout`.
+    pub unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        let Some(index) = list_index(&layout) else {
+            self.fallback_allocator.deallocate(ptr, layout);
+            self.large.deallocations += 1;
+            return;
+        };
+        // verify that block has size and alignment required for storing node
+        assert!(mem::size_of::<ListNode>() <= BLOCK_SIZES[index]);
+        assert!(mem::align_of::<ListNode>() <= BLOCK_SIZES[index]);
+        let new_node_ptr = ptr.as_ptr() as *mut ListNode;
+        new_node_ptr.write(ListNode {
+            next: self.list_heads[index].take(),
+        });
+        self.list_heads[index] = Some(&mut *new_node_ptr);
+        self.size_classes[index].deallocations += 1;
+        self.size_classes[index].free_blocks += 1;
+    }
+}
+
+/// Choose an appropriate block size for the given layout.
+///
+/// Returns an index into the `BLOCK_SIZES` array.
+fn list_index(layout: &Layout) -> Option<usize> {
+    let required_block_size = layout.size().max(layout.align());
+    BLOCK_SIZES.iter().position(|&s| s >= required_block_size)
+}
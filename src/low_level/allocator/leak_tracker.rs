@@ -0,0 +1,117 @@
+use core::arch::asm;
+use spin::Mutex;
+
+use crate::println;
+
+/// How many live allocations can be tracked at once. Allocations made while the table
+/// is full are counted, but their call sites are lost.
+const MAX_TRACKED: usize = 1024;
+/// How many return addresses are recorded per allocation. The first few frames are
+/// always inside `alloc`, so this has to reach past them to be useful.
+const CALL_STACK_DEPTH: usize = 6;
+
+static TRACKER: Mutex<LeakTracker> = Mutex::new(LeakTracker::new());
+
+#[derive(Clone, Copy)]
+struct Record {
+    address: usize,
+    size: usize,
+    generation: usize,
+    call_stack: [usize; CALL_STACK_DEPTH],
+}
+
+struct LeakTracker {
+    records: [Option<Record>; MAX_TRACKED],
+    generation: usize,
+    untracked: usize,
+}
+
+impl LeakTracker {
+    const fn new() -> Self {
+        LeakTracker {
+            records: [None; MAX_TRACKED],
+            generation: 0,
+            untracked: 0,
+        }
+    }
+}
+
+pub fn record_allocation(address: usize, size: usize) {
+    let call_stack = call_stack();
+    let mut tracker = TRACKER.lock();
+    let generation = tracker.generation;
+    match tracker.records.iter_mut().find(|record| record.is_none()) {
+        Some(slot) => {
+            *slot = Some(Record {
+                address,
+                size,
+                generation,
+                call_stack,
+            })
+        }
+        None => tracker.untracked += 1,
+    }
+}
+
+pub fn record_deallocation(address: usize) {
+    let mut tracker = TRACKER.lock();
+    let record = tracker
+        .records
+        .iter_mut()
+        .find(|record| record.is_some_and(|record| record.address == address));
+    match record {
+        Some(record) => *record = None,
+        None => tracker.untracked = tracker.untracked.saturating_sub(1),
+    }
+}
+
+/// Marks the current point in time; `dump_leaks` only reports allocations made after it.
+pub fn leak_checkpoint() {
+    TRACKER.lock().generation += 1;
+}
+
+/// Prints every allocation made since the last `leak_checkpoint` that is still alive,
+/// with the return addresses leading to it. Resolve them with `addr2line` against the
+/// kernel binary.
+pub fn dump_leaks() {
+    let tracker = TRACKER.lock();
+    let outstanding = tracker
+        .records
+        .iter()
+        .flatten()
+        .filter(|record| record.generation == tracker.generation);
+    let mut count = 0;
+    for record in outstanding {
+        count += 1;
+        println!(
+            "{:#x} ({} bytes) allocated from {:x?}",
+            record.address, record.size, record.call_stack
+        );
+    }
+    println!(
+        "{} outstanding allocations since checkpoint, {} untracked",
+        count, tracker.untracked
+    );
+}
+
+/// Walks the frame pointer chain. Only reliable because the kernel is built with
+/// `-C force-frame-pointers=yes`.
+fn call_stack() -> [usize; CALL_STACK_DEPTH] {
+    let mut call_stack = [0; CALL_STACK_DEPTH];
+    let mut frame_pointer: usize;
+    unsafe { asm!("mov {}, rbp", out(reg) frame_pointer) };
+    for entry in call_stack.iter_mut() {
+        if frame_pointer == 0 || !frame_pointer.is_multiple_of(8) {
+            break;
+        }
+        let frame = frame_pointer as *const usize;
+        let (next_frame_pointer, return_address) = unsafe { (*frame, *frame.add(1)) };
+        *entry = return_address;
+        // frames further up the stack always have higher addresses
+        if next_frame_pointer <= frame_pointer {
+            break;
+        }
+        frame_pointer = next_frame_pointer;
+    }
+    call_stack
+}
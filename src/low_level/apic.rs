@@ -0,0 +1,277 @@
+//Local APIC and I/O APIC support. When the CPU has an APIC, init() masks the 8259 PICs,
+//routes the legacy IRQs through the I/O APIC and drives the timer interrupt from the
+//local APIC timer. Without one, everything stays on the PICs.
+use core::arch::x86_64::__cpuid;
+use spin::Once;
+use x86_64::{
+    registers::model_specific::Msr,
+    structures::paging::{mapper::MapToError, Size4KiB},
+    PhysAddr, VirtAddr,
+};
+
+use crate::low_level::{
+    interrupts::{InterruptIndex, PICS},
+    memory::MEMORY,
+    sync::IrqSafeMutex,
+    timer,
+};
+
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+pub const DEFAULT_IO_APIC_ADDRESS: u64 = 0xFEC0_0000;
+
+const IA32_APIC_BASE_MSR: u32 = 0x1B;
+const APIC_BASE_X2APIC_ENABLE: u64 = 1 << 10;
+const APIC_BASE_GLOBAL_ENABLE: u64 = 1 << 11;
+const X2APIC_MSR_BASE: u32 = 0x800;
+
+const LAPIC_ID: u32 = 0x20;
+const LAPIC_TASK_PRIORITY: u32 = 0x80;
+const LAPIC_EOI: u32 = 0xB0;
+const LAPIC_SPURIOUS_VECTOR: u32 = 0xF0;
+const LAPIC_LVT_TIMER: u32 = 0x320;
+const LAPIC_TIMER_INITIAL_COUNT: u32 = 0x380;
+const LAPIC_TIMER_CURRENT_COUNT: u32 = 0x390;
+const LAPIC_TIMER_DIVIDE: u32 = 0x3E0;
+
+const SOFTWARE_ENABLE: u32 = 1 << 8;
+const TIMER_PERIODIC: u32 = 1 << 17;
+const LVT_MASKED: u32 = 1 << 16;
+const TIMER_DIVIDE_BY_16: u32 = 0b0011;
+const CALIBRATION_MS: u16 = 10;
+
+const IO_APIC_VERSION: u32 = 0x01;
+const IO_APIC_REDIRECTION_TABLE: u32 = 0x10;
+const REDIRECTION_ACTIVE_LOW: u64 = 1 << 13;
+const REDIRECTION_LEVEL_TRIGGERED: u64 = 1 << 15;
+
+static LOCAL_APIC: Once<LocalApic> = Once::new();
+static IO_APIC: IrqSafeMutex<Option<IoApic>> = IrqSafeMutex::new(None);
+
+/// Where the I/O APIC is and how ISA IRQs map onto its inputs. Comes from
+/// `acpi::Madt::io_apic_config`, `IoApicConfig::default()` is what PCs use without a MADT.
+pub struct IoApicConfig<'a> {
+    pub address: PhysAddr,
+    pub gsi_base: u32,
+    pub overrides: &'a [InterruptOverride],
+}
+
+impl Default for IoApicConfig<'_> {
+    fn default() -> Self {
+        IoApicConfig {
+            address: PhysAddr::new(DEFAULT_IO_APIC_ADDRESS),
+            gsi_base: 0,
+            overrides: &[],
+        }
+    }
+}
+
+/// An ISA IRQ that is not wired to the I/O APIC input with the same number, or that does
+/// not use the ISA default of active high, edge triggered.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InterruptOverride {
+    pub irq: u8,
+    pub gsi: u32,
+    pub active_low: bool,
+    pub level_triggered: bool,
+}
+
+enum LocalApic {
+    XApic(VirtAddr),
+    X2Apic,
+}
+
+impl LocalApic {
+    unsafe fn read(&self, register: u32) -> u32 {
+        match self {
+            LocalApic::XApic(base) => (*base + u64::from(register))
+                .as_ptr::<u32>()
+                .read_volatile(),
+            LocalApic::X2Apic => Msr::new(X2APIC_MSR_BASE + (register >> 4)).read() as u32,
+        }
+    }
+
+    unsafe fn write(&self, register: u32, value: u32) {
+        match self {
+            LocalApic::XApic(base) => (*base + u64::from(register))
+                .as_mut_ptr::<u32>()
+                .write_volatile(value),
+            LocalApic::X2Apic => Msr::new(X2APIC_MSR_BASE + (register >> 4)).write(value.into()),
+        }
+    }
+
+    fn id(&self) -> u32 {
+        let id = unsafe { self.read(LAPIC_ID) };
+        match self {
+            LocalApic::XApic(_) => id >> 24,
+            LocalApic::X2Apic => id,
+        }
+    }
+}
+
+struct IoApic {
+    base: VirtAddr,
+    gsi_base: u32,
+    overrides: [Option<InterruptOverride>; 16],
+}
+
+impl IoApic {
+    unsafe fn read(&self, register: u32) -> u32 {
+        self.base.as_mut_ptr::<u32>().write_volatile(register);
+        (self.base + 0x10u64).as_ptr::<u32>().read_volatile()
+    }
+
+    unsafe fn write(&self, register: u32, value: u32) {
+        self.base.as_mut_ptr::<u32>().write_volatile(register);
+        (self.base + 0x10u64)
+            .as_mut_ptr::<u32>()
+            .write_volatile(value);
+    }
+
+    fn input_count(&self) -> u32 {
+        (unsafe { self.read(IO_APIC_VERSION) } >> 16 & 0xFF) + 1
+    }
+
+    fn set_redirection(&self, input: u32, entry: u64) {
+        let register = IO_APIC_REDIRECTION_TABLE + input * 2;
+        unsafe {
+            self.write(register, entry as u32);
+            self.write(register + 1, (entry >> 32) as u32);
+        }
+    }
+}
+
+pub fn is_supported() -> bool {
+    let features = __cpuid(1);
+    features.edx & (1 << 9) != 0
+}
+
+fn is_x2apic_supported() -> bool {
+    let features = __cpuid(1);
+    features.ecx & (1 << 21) != 0
+}
+
+/// Whether interrupts are delivered through the APIC. Until `init` succeeds, the 8259 PICs
+/// are used.
+pub fn is_enabled() -> bool {
+    LOCAL_APIC.get().is_some()
+}
+
+/// Switches interrupt delivery from the PICs to the APIC if the CPU has one. Has to run
+/// with interrupts disabled, after the PICs were initialized and `memory::MEMORY` is set up.
+pub fn init(io_apic_config: &IoApicConfig) -> Result<(), MapToError<Size4KiB>> {
+    if !is_supported() || is_enabled() {
+        return Ok(());
+    }
+    let local_apic = enable_local_apic()?;
+    let io_apic = map_io_apic(io_apic_config)?;
+
+    unsafe { PICS.lock().disable() };
+    for input in 0..io_apic.input_count() {
+        io_apic.set_redirection(input, LVT_MASKED.into());
+    }
+    let local_apic = LOCAL_APIC.call_once(|| local_apic);
+    *IO_APIC.lock() = Some(io_apic);
+    for index in InterruptIndex::ISA_INTERRUPTS {
+        route_isa_irq(index);
+    }
+    start_timer(local_apic);
+    Ok(())
+}
+
+fn enable_local_apic() -> Result<LocalApic, MapToError<Size4KiB>> {
+    let mut apic_base_msr = Msr::new(IA32_APIC_BASE_MSR);
+    let apic_base = unsafe { apic_base_msr.read() };
+    let local_apic = if is_x2apic_supported() {
+        let enable = APIC_BASE_GLOBAL_ENABLE | APIC_BASE_X2APIC_ENABLE;
+        unsafe { apic_base_msr.write(apic_base | enable) };
+        LocalApic::X2Apic
+    } else {
+        unsafe { apic_base_msr.write(apic_base | APIC_BASE_GLOBAL_ENABLE) };
+        let physical_address = PhysAddr::new(apic_base & 0x000F_FFFF_FFFF_F000);
+        let mut memory = MEMORY.lock();
+        let memory = memory.as_mut().ok_or(MapToError::FrameAllocationFailed)?;
+        LocalApic::XApic(memory.map_mmio(physical_address, 0x400)?)
+    };
+    unsafe {
+        local_apic.write(LAPIC_TASK_PRIORITY, 0);
+        local_apic.write(
+            LAPIC_SPURIOUS_VECTOR,
+            SOFTWARE_ENABLE | u32::from(SPURIOUS_VECTOR),
+        );
+    }
+    Ok(local_apic)
+}
+
+fn map_io_apic(config: &IoApicConfig) -> Result<IoApic, MapToError<Size4KiB>> {
+    let mut memory = MEMORY.lock();
+    let memory = memory.as_mut().ok_or(MapToError::FrameAllocationFailed)?;
+    let mut overrides = [None; 16];
+    for interrupt_override in config.overrides.iter().filter(|o| o.irq < 16) {
+        overrides[usize::from(interrupt_override.irq)] = Some(*interrupt_override);
+    }
+    Ok(IoApic {
+        base: memory.map_mmio(config.address, 0x20)?,
+        gsi_base: config.gsi_base,
+        overrides,
+    })
+}
+
+/// Delivers the ISA IRQ behind `index` to the vector `index` through the I/O APIC,
+/// honoring the MADT overrides. Does nothing while the PICs are in use.
+pub fn route_isa_irq(index: InterruptIndex) {
+    let (Some(local_apic), Some(io_apic)) = (LOCAL_APIC.get(), &*IO_APIC.lock()) else {
+        return;
+    };
+    let irq = index.isa_irq();
+    let (gsi, mut entry) = match io_apic.overrides[usize::from(irq)] {
+        Some(interrupt_override) => {
+            let mut flags = 0;
+            if interrupt_override.active_low {
+                flags |= REDIRECTION_ACTIVE_LOW;
+            }
+            if interrupt_override.level_triggered {
+                flags |= REDIRECTION_LEVEL_TRIGGERED;
+            }
+            (interrupt_override.gsi, flags)
+        }
+        None => (u32::from(irq), 0),
+    };
+    let Some(input) = gsi.checked_sub(io_apic.gsi_base) else {
+        return;
+    };
+    entry |= u64::from(index.as_u8()) | u64::from(local_apic.id() & 0xFF) << 56;
+    io_apic.set_redirection(input, entry);
+}
+
+/// Calibrates the local APIC timer against the PIT and starts it at `TIMER_FREQUENCY`.
+fn start_timer(local_apic: &LocalApic) {
+    unsafe {
+        local_apic.write(LAPIC_TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+        local_apic.write(LAPIC_LVT_TIMER, LVT_MASKED);
+        local_apic.write(LAPIC_TIMER_INITIAL_COUNT, u32::MAX);
+        timer::pit_busy_wait(CALIBRATION_MS);
+        let elapsed = u32::MAX - local_apic.read(LAPIC_TIMER_CURRENT_COUNT);
+        local_apic.write(LAPIC_TIMER_INITIAL_COUNT, 0);
+
+        let counts_per_second = u64::from(elapsed) * 1000 / u64::from(CALIBRATION_MS);
+        let initial_count = (counts_per_second / u64::from(timer::TIMER_FREQUENCY)).max(1);
+        local_apic.write(
+            LAPIC_LVT_TIMER,
+            TIMER_PERIODIC | u32::from(InterruptIndex::Timer.as_u8()),
+        );
+        local_apic.write(LAPIC_TIMER_INITIAL_COUNT, initial_count as u32);
+    }
+    timer::set_tick_frequency(timer::TIMER_FREQUENCY);
+}
+
+/// The local APIC id of the CPU running this, 0 while the APIC is not in use.
+pub fn current_cpu_id() -> u32 {
+    LOCAL_APIC.get().map_or(0, LocalApic::id)
+}
+
+/// Signals the end of the current interrupt to the local APIC.
+pub fn end_of_interrupt() {
+    if let Some(local_apic) = LOCAL_APIC.get() {
+        unsafe { local_apic.write(LAPIC_EOI, 0) };
+    }
+}
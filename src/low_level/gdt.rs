@@ -1,32 +1,55 @@
-use lazy_static::lazy_static;
+use spin::Once;
 use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector};
+use x86_64::structures::paging::{
+    mapper::MapToError, FrameAllocator, Mapper, Page, PageSize, PageTableFlags, Size4KiB,
+};
 use x86_64::structures::tss::TaskStateSegment;
 use x86_64::VirtAddr;
 
-pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+use crate::low_level::memory::MEMORY;
 
-lazy_static! {
-    static ref TSS: TaskStateSegment = {
-        let mut tss = TaskStateSegment::new();
-        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = 4096 * 5;
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+pub const PAGE_FAULT_IST_INDEX: u16 = 1;
+pub const NMI_IST_INDEX: u16 = 2;
+pub const MACHINE_CHECK_IST_INDEX: u16 = 3;
 
-            let stack_start = VirtAddr::from_ptr(unsafe { &STACK });
+const IST_STACK_NAMES: [&str; 4] = ["double fault", "page fault", "NMI", "machine check"];
+const IST_STACKS_START: u64 = 0x_5555_5555_0000;
+const IST_STACK_PAGES: u64 = 5; // 20 KiB
+/// Every stack is preceded by one unmapped guard page, so running off the end of a stack
+/// page faults instead of silently overwriting whatever is below it.
+const IST_SLOT_SIZE: u64 = (IST_STACK_PAGES + 1) * Size4KiB::SIZE;
 
-            //stack end!
-            stack_start + STACK_SIZE
+static TSS: Once<TaskStateSegment> = Once::new();
+static GDT: Once<(GlobalDescriptorTable, Selectors)> = Once::new();
 
-        };
-        tss
-    };
+struct Selectors {
+    code_selector: SegmentSelector,
+    tss_selector: SegmentSelector,
 }
 
-lazy_static! {
-    static ref GDT: (GlobalDescriptorTable, Selectors) = {
+/// Maps the interrupt stacks and loads the GDT and TSS. Needs `memory::MEMORY` to be set up.
+pub fn init() {
+    use x86_64::instructions::segmentation::{Segment, CS};
+    use x86_64::instructions::tables::load_tss;
+
+    let tss = TSS.call_once(|| {
+        let mut tss = TaskStateSegment::new();
+        let mut memory = MEMORY.lock();
+        let memory = memory
+            .as_mut()
+            .expect("memory has to be initialized before the GDT");
+        for (index, _) in IST_STACK_NAMES.iter().enumerate() {
+            tss.interrupt_stack_table[index] =
+                map_ist_stack(index, &mut memory.mapper, &mut memory.frame_allocator)
+                    .expect("mapping interrupt stacks failed");
+        }
+        tss
+    });
+    let (gdt, selectors) = GDT.call_once(|| {
         let mut gdt = GlobalDescriptorTable::new();
         let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
-        let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
+        let tss_selector = gdt.add_entry(Descriptor::tss_segment(tss));
         (
             gdt,
             Selectors {
@@ -34,21 +57,42 @@ lazy_static! {
                 tss_selector,
             },
         )
-    };
-}
+    });
 
-struct Selectors {
-    code_selector: SegmentSelector,
-    tss_selector: SegmentSelector,
+    gdt.load();
+    unsafe {
+        CS::set_reg(selectors.code_selector);
+        load_tss(selectors.tss_selector);
+    }
 }
 
-pub fn init() {
-    use x86_64::instructions::segmentation::{Segment, CS};
-    use x86_64::instructions::tables::load_tss;
-
-    GDT.0.load();
-    unsafe {
-        CS::set_reg(GDT.1.code_selector);
-        load_tss(GDT.1.tss_selector);
+/// Maps the stack for IST slot `index` and returns its end, which is what the TSS wants.
+fn map_ist_stack(
+    index: usize,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<VirtAddr, MapToError<Size4KiB>> {
+    let guard_page = VirtAddr::new(IST_STACKS_START + index as u64 * IST_SLOT_SIZE);
+    let stack_start = Page::containing_address(guard_page + Size4KiB::SIZE);
+    let stack_end = stack_start + IST_STACK_PAGES;
+    for page in Page::range(stack_start, stack_end) {
+        let frame = frame_allocator
+            .allocate_frame()
+            .ok_or(MapToError::FrameAllocationFailed)?;
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
     }
+    Ok(stack_end.start_address())
+}
+
+/// If `address` lies in the guard page below one of the interrupt stacks, returns the
+/// name of that stack.
+pub fn ist_guard_page_hit(address: u64) -> Option<&'static str> {
+    let offset = address.checked_sub(IST_STACKS_START)?;
+    let index = (offset / IST_SLOT_SIZE) as usize;
+    let is_guard_page = offset % IST_SLOT_SIZE < Size4KiB::SIZE;
+    IST_STACK_NAMES
+        .get(index)
+        .filter(|_| is_guard_page)
+        .copied()
 }
@@ -1,32 +1,48 @@
+use core::sync::atomic::{AtomicU64, Ordering};
 use lazy_static::lazy_static;
-use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+use x86_64::{
+    structures::idt::{InterruptDescriptorTable, InterruptStackFrame},
+    VirtAddr,
+};
 
-use crate::{
-    hlt_loop,
-    low_level::gdt,
-    println,
-    userspace::user_interface::{handle_keypress, handle_raw_keypress},
+use crate::low_level::{
+    apic, mouse, ps2,
+    scheduler::{self, context},
+    sync::IrqSafeMutex,
+    task::keyboard,
+    timer,
 };
 use pic8259::ChainedPics;
-use spin;
+
+pub use exceptions::SelectorErrorCode;
+
+mod exceptions;
+
 pub const PIC_1_OFFSET: u8 = 32;
 pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
+/// The input of the first PIC the second one is wired to.
+const PIC_CASCADE_IRQ: u8 = 2;
 
-pub static PICS: spin::Mutex<ChainedPics> =
-    spin::Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
+pub static PICS: IrqSafeMutex<ChainedPics> =
+    IrqSafeMutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
+
+/// How often each hardware interrupt vector fired since boot.
+static INTERRUPT_COUNTS: [AtomicU64; 256] = [const { AtomicU64::new(0) }; 256];
 
 lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
-        idt.breakpoint.set_handler_fn(breakpoint_handler);
+        exceptions::set_exception_handlers(&mut idt);
+        // these two enter through the scheduler's stubs, which can switch threads
         unsafe {
-            idt.double_fault
-                .set_handler_fn(double_fault_handler)
-                .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+            idt[InterruptIndex::Timer.as_usize()]
+                .set_handler_addr(VirtAddr::from_ptr(context::timer_interrupt_entry as *const ()));
+            idt[usize::from(scheduler::YIELD_VECTOR)]
+                .set_handler_addr(VirtAddr::from_ptr(context::yield_interrupt_entry as *const ()));
         }
-        idt[InterruptIndex::Timer.as_usize()].set_handler_fn(timer_interrupt_handler);
         idt[InterruptIndex::Keyboard.as_usize()].set_handler_fn(keyboard_interrupt_handler);
-        idt.page_fault.set_handler_fn(page_fault_handler);
+        idt[InterruptIndex::Mouse.as_usize()].set_handler_fn(mouse_interrupt_handler);
+        idt[usize::from(apic::SPURIOUS_VECTOR)].set_handler_fn(spurious_interrupt_handler);
         idt
     };
 }
@@ -35,68 +51,79 @@ pub fn init_idt() {
     IDT.load();
 }
 
-extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
+/// How often the interrupt with `vector` fired since boot. Only hardware interrupts are counted.
+pub fn interrupt_count(vector: u8) -> u64 {
+    INTERRUPT_COUNTS[usize::from(vector)].load(Ordering::Relaxed)
 }
 
-// new
-extern "x86-interrupt" fn double_fault_handler(
-    stack_frame: InterruptStackFrame,
-    _error_code: u64,
-) -> ! {
-    panic!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
+fn count_interrupt(vector: u8) {
+    INTERRUPT_COUNTS[usize::from(vector)].fetch_add(1, Ordering::Relaxed);
 }
 
-extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
-    }
+/// Takes the stack pointer of the interrupted thread and returns the one to resume,
+/// see `scheduler::context`.
+pub(crate) extern "C" fn timer_interrupt_handler(stack_pointer: u64) -> u64 {
+    count_interrupt(InterruptIndex::Timer.as_u8());
+    timer::tick();
+    end_of_interrupt(InterruptIndex::Timer);
+    scheduler::on_timer_tick(stack_pointer)
 }
 
+pub(crate) extern "C" fn yield_interrupt_handler(stack_pointer: u64) -> u64 {
+    scheduler::on_yield(stack_pointer)
+}
+
+/// Only reads the scancode, see `task::keyboard` for what happens with it.
 extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
-    use spin::Mutex;
     use x86_64::instructions::port::Port;
 
-    lazy_static! {
-        static ref KEYBOARD: Mutex<Keyboard<layouts::Us104Key, ScancodeSet1>> =
-            Mutex::new(Keyboard::new(
-                ScancodeSet1::new(),
-                layouts::Us104Key,
-                HandleControl::Ignore
-            ));
-    }
-
-    let mut keyboard = KEYBOARD.lock();
+    count_interrupt(InterruptIndex::Keyboard.as_u8());
     let mut port = Port::new(0x60);
-
     let scancode: u8 = unsafe { port.read() };
-    if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
-        if let Some(key) = keyboard.process_keyevent(key_event) {
-            match key {
-                DecodedKey::Unicode(character) => handle_keypress(character),
-                DecodedKey::RawKey(keycode) => handle_raw_keypress(keycode),
-            }
-        }
-    }
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Keyboard.as_u8());
+    keyboard::add_scancode(scancode);
+    end_of_interrupt(InterruptIndex::Keyboard);
+}
+
+/// Passes the byte on to `mouse`, which queues the event once a packet is complete.
+extern "x86-interrupt" fn mouse_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    use x86_64::instructions::port::Port;
+
+    count_interrupt(InterruptIndex::Mouse.as_u8());
+    let byte: u8 = unsafe { Port::new(ps2::DATA_PORT).read() };
+    mouse::add_byte(byte);
+    end_of_interrupt(InterruptIndex::Mouse);
+}
+
+/// The local APIC raises this when an interrupt went away before it could be delivered.
+/// It must not be acknowledged.
+extern "x86-interrupt" fn spurious_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    count_interrupt(apic::SPURIOUS_VECTOR);
+}
+
+/// Acknowledges `index` at whichever interrupt controller is in use.
+pub fn end_of_interrupt(index: InterruptIndex) {
+    if apic::is_enabled() {
+        apic::end_of_interrupt();
+    } else {
+        unsafe { PICS.lock().notify_end_of_interrupt(index.as_u8()) };
     }
 }
 
-extern "x86-interrupt" fn page_fault_handler(
-    stack_frame: InterruptStackFrame,
-    error_code: PageFaultErrorCode,
-) {
-    use x86_64::registers::control::Cr2;
-
-    println!("EXCEPTION: PAGE FAULT");
-    println!("Accessed Address: {:?}", Cr2::read());
-    println!("Error Code: {:?}", error_code);
-    println!("{:#?}", stack_frame);
-    hlt_loop();
+/// Lets `index` through the PICs, which otherwise keep the mask the BIOS left. Interrupts
+/// on the second PIC also need the cascade input of the first one unmasked.
+pub fn unmask_pic_interrupt(index: InterruptIndex) {
+    let irq = index.isa_irq();
+    let mut pics = PICS.lock();
+    unsafe {
+        let [mut mask1, mut mask2] = pics.read_masks();
+        if irq < 8 {
+            mask1 &= !(1 << irq);
+        } else {
+            mask1 &= !(1 << PIC_CASCADE_IRQ);
+            mask2 &= !(1 << (irq - 8));
+        }
+        pics.write_masks(mask1, mask2);
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -104,14 +131,31 @@ extern "x86-interrupt" fn page_fault_handler(
 pub enum InterruptIndex {
     Timer = PIC_1_OFFSET,
     Keyboard,
+    Mouse = PIC_2_OFFSET + 4,
 }
 
 impl InterruptIndex {
-    fn as_u8(self) -> u8 {
+    pub const ALL: [InterruptIndex; 3] = [
+        InterruptIndex::Timer,
+        InterruptIndex::Keyboard,
+        InterruptIndex::Mouse,
+    ];
+
+    /// The ISA interrupts the I/O APIC routes. The PIT stays masked there, because with an
+    /// APIC the timer interrupt comes from the local APIC timer instead.
+    pub const ISA_INTERRUPTS: [InterruptIndex; 2] =
+        [InterruptIndex::Keyboard, InterruptIndex::Mouse];
+
+    pub fn as_u8(self) -> u8 {
         self as u8
     }
 
     fn as_usize(self) -> usize {
         usize::from(self.as_u8())
     }
+
+    /// The ISA IRQ line, which is also the 8259 input.
+    pub fn isa_irq(self) -> u8 {
+        self.as_u8() - PIC_1_OFFSET
+    }
 }
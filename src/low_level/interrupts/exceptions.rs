@@ -0,0 +1,329 @@
+use core::fmt;
+use x86_64::{
+    registers::control::Cr2,
+    structures::{
+        idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode},
+        paging::{PageSize, Size4KiB},
+    },
+};
+
+use crate::{
+    low_level::{
+        gdt,
+        panic_screen::{capture_exception, ErrorCode},
+    },
+    println,
+    userspace::locale::message,
+};
+
+pub(super) fn set_exception_handlers(idt: &mut InterruptDescriptorTable) {
+    idt.divide_error.set_handler_fn(divide_error_handler);
+    idt.debug.set_handler_fn(debug_handler);
+    idt.breakpoint.set_handler_fn(breakpoint_handler);
+    idt.overflow.set_handler_fn(overflow_handler);
+    idt.bound_range_exceeded
+        .set_handler_fn(bound_range_exceeded_handler);
+    idt.invalid_opcode.set_handler_fn(invalid_opcode_handler);
+    idt.device_not_available
+        .set_handler_fn(device_not_available_handler);
+    unsafe {
+        idt.double_fault
+            .set_handler_fn(double_fault_handler)
+            .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+        idt.page_fault
+            .set_handler_fn(page_fault_handler)
+            .set_stack_index(gdt::PAGE_FAULT_IST_INDEX);
+        idt.non_maskable_interrupt
+            .set_handler_fn(non_maskable_interrupt_handler)
+            .set_stack_index(gdt::NMI_IST_INDEX);
+        idt.machine_check
+            .set_handler_fn(machine_check_handler)
+            .set_stack_index(gdt::MACHINE_CHECK_IST_INDEX);
+    }
+    idt.invalid_tss.set_handler_fn(invalid_tss_handler);
+    idt.segment_not_present
+        .set_handler_fn(segment_not_present_handler);
+    idt.stack_segment_fault
+        .set_handler_fn(stack_segment_fault_handler);
+    idt.general_protection_fault
+        .set_handler_fn(general_protection_fault_handler);
+    idt.x87_floating_point
+        .set_handler_fn(x87_floating_point_handler);
+    idt.alignment_check.set_handler_fn(alignment_check_handler);
+    idt.simd_floating_point
+        .set_handler_fn(simd_floating_point_handler);
+    idt.virtualization.set_handler_fn(virtualization_handler);
+    idt.cp_protection_exception
+        .set_handler_fn(control_protection_handler);
+    idt.hv_injection_exception
+        .set_handler_fn(hypervisor_injection_handler);
+    idt.vmm_communication_exception
+        .set_handler_fn(vmm_communication_handler);
+    idt.security_exception
+        .set_handler_fn(security_exception_handler);
+}
+
+/// Saves the exception state for the panic screen and panics with the vector name.
+fn fatal_exception(
+    stack_frame: &InterruptStackFrame,
+    vector: u8,
+    name: &str,
+    error_code: ErrorCode,
+) -> ! {
+    capture_exception(stack_frame, error_code);
+    panic!(
+        "{}: {} (vector {}, error code {:?})",
+        message("exception.prefix"),
+        name,
+        vector,
+        error_code
+    );
+}
+
+/// The error code pushed by #TS, #NP, #SS and #GP when the fault is caused by a segment
+/// selector. Zero means the fault was not related to a selector.
+#[derive(Clone, Copy)]
+pub struct SelectorErrorCode(pub u64);
+
+impl SelectorErrorCode {
+    /// The event that caused the fault came from outside the CPU, e.g. a hardware interrupt.
+    pub fn external(&self) -> bool {
+        self.0 & 0b1 != 0
+    }
+
+    pub fn table(&self) -> &'static str {
+        match (self.0 >> 1) & 0b11 {
+            0b00 => "GDT",
+            0b10 => "LDT",
+            _ => "IDT",
+        }
+    }
+
+    pub fn index(&self) -> u64 {
+        (self.0 >> 3) & 0x1FFF
+    }
+}
+
+impl fmt::Debug for SelectorErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#x} ({}[{}]", self.0, self.table(), self.index())?;
+        if self.external() {
+            write!(f, ", external")?;
+        }
+        write!(f, ")")
+    }
+}
+
+extern "x86-interrupt" fn divide_error_handler(stack_frame: InterruptStackFrame) {
+    fatal_exception(&stack_frame, 0, "DIVIDE ERROR", ErrorCode::None);
+}
+
+extern "x86-interrupt" fn debug_handler(stack_frame: InterruptStackFrame) {
+    println!("{}: DEBUG\n{:#?}", message("exception.prefix"), stack_frame);
+}
+
+extern "x86-interrupt" fn non_maskable_interrupt_handler(stack_frame: InterruptStackFrame) {
+    fatal_exception(&stack_frame, 2, "NON-MASKABLE INTERRUPT", ErrorCode::None);
+}
+
+extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
+    println!(
+        "{}: BREAKPOINT\n{:#?}",
+        message("exception.prefix"),
+        stack_frame
+    );
+}
+
+extern "x86-interrupt" fn overflow_handler(stack_frame: InterruptStackFrame) {
+    fatal_exception(&stack_frame, 4, "OVERFLOW", ErrorCode::None);
+}
+
+extern "x86-interrupt" fn bound_range_exceeded_handler(stack_frame: InterruptStackFrame) {
+    fatal_exception(&stack_frame, 5, "BOUND RANGE EXCEEDED", ErrorCode::None);
+}
+
+extern "x86-interrupt" fn invalid_opcode_handler(stack_frame: InterruptStackFrame) {
+    fatal_exception(&stack_frame, 6, "INVALID OPCODE", ErrorCode::None);
+}
+
+extern "x86-interrupt" fn device_not_available_handler(stack_frame: InterruptStackFrame) {
+    fatal_exception(&stack_frame, 7, "DEVICE NOT AVAILABLE", ErrorCode::None);
+}
+
+extern "x86-interrupt" fn double_fault_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) -> ! {
+    // CR2 may still hold the address of some earlier, unrelated page fault, so only the
+    // stack pointer tells whether a stack ran into its guard page: the push that faulted
+    // wrote just below it
+    let stack_pointer = stack_frame.stack_pointer.as_u64();
+    let name = match gdt::ist_guard_page_hit(stack_pointer.wrapping_sub(8)).is_some() {
+        true => "DOUBLE FAULT, KERNEL STACK OVERFLOW",
+        false => "DOUBLE FAULT",
+    };
+    fatal_exception(&stack_frame, 8, name, ErrorCode::Code(error_code));
+}
+
+extern "x86-interrupt" fn invalid_tss_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+    let error_code = ErrorCode::Selector(SelectorErrorCode(error_code));
+    fatal_exception(&stack_frame, 10, "INVALID TSS", error_code);
+}
+
+extern "x86-interrupt" fn segment_not_present_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) {
+    let error_code = ErrorCode::Selector(SelectorErrorCode(error_code));
+    fatal_exception(&stack_frame, 11, "SEGMENT NOT PRESENT", error_code);
+}
+
+extern "x86-interrupt" fn stack_segment_fault_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) {
+    let error_code = ErrorCode::Selector(SelectorErrorCode(error_code));
+    fatal_exception(&stack_frame, 12, "STACK-SEGMENT FAULT", error_code);
+}
+
+extern "x86-interrupt" fn general_protection_fault_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) {
+    let error_code = ErrorCode::Selector(SelectorErrorCode(error_code));
+    fatal_exception(&stack_frame, 13, "GENERAL PROTECTION FAULT", error_code);
+}
+
+extern "x86-interrupt" fn page_fault_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: PageFaultErrorCode,
+) {
+    let is_stack_overflow = !error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION)
+        && is_stack_overflow(&stack_frame, Cr2::read_raw());
+    let name = match is_stack_overflow {
+        true => "PAGE FAULT, KERNEL STACK OVERFLOW",
+        false => "PAGE FAULT",
+    };
+    fatal_exception(&stack_frame, 14, name, ErrorCode::PageFault(error_code));
+}
+
+/// Guesses whether a page fault at `accessed_address` was caused by a stack running into its
+/// guard page: either the address is in the guard page of an interrupt stack, or it is within
+/// a page of the stack pointer at the time of the fault, which is where pushes and stack
+/// frame setup write to.
+fn is_stack_overflow(stack_frame: &InterruptStackFrame, accessed_address: u64) -> bool {
+    let stack_pointer = stack_frame.stack_pointer.as_u64();
+    gdt::ist_guard_page_hit(accessed_address).is_some()
+        || accessed_address.abs_diff(stack_pointer) < Size4KiB::SIZE
+}
+
+extern "x86-interrupt" fn x87_floating_point_handler(stack_frame: InterruptStackFrame) {
+    fatal_exception(
+        &stack_frame,
+        16,
+        "x87 FLOATING-POINT EXCEPTION",
+        ErrorCode::None,
+    );
+}
+
+extern "x86-interrupt" fn alignment_check_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) {
+    fatal_exception(
+        &stack_frame,
+        17,
+        "ALIGNMENT CHECK",
+        ErrorCode::Code(error_code),
+    );
+}
+
+extern "x86-interrupt" fn machine_check_handler(stack_frame: InterruptStackFrame) -> ! {
+    fatal_exception(&stack_frame, 18, "MACHINE CHECK", ErrorCode::None);
+}
+
+extern "x86-interrupt" fn simd_floating_point_handler(stack_frame: InterruptStackFrame) {
+    fatal_exception(
+        &stack_frame,
+        19,
+        "SIMD FLOATING-POINT EXCEPTION",
+        ErrorCode::None,
+    );
+}
+
+extern "x86-interrupt" fn virtualization_handler(stack_frame: InterruptStackFrame) {
+    fatal_exception(
+        &stack_frame,
+        20,
+        "VIRTUALIZATION EXCEPTION",
+        ErrorCode::None,
+    );
+}
+
+extern "x86-interrupt" fn control_protection_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) {
+    fatal_exception(
+        &stack_frame,
+        21,
+        "CONTROL PROTECTION EXCEPTION",
+        ErrorCode::Code(error_code),
+    );
+}
+
+extern "x86-interrupt" fn hypervisor_injection_handler(stack_frame: InterruptStackFrame) {
+    fatal_exception(
+        &stack_frame,
+        28,
+        "HYPERVISOR INJECTION EXCEPTION",
+        ErrorCode::None,
+    );
+}
+
+extern "x86-interrupt" fn vmm_communication_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) {
+    fatal_exception(
+        &stack_frame,
+        29,
+        "VMM COMMUNICATION EXCEPTION",
+        ErrorCode::Code(error_code),
+    );
+}
+
+extern "x86-interrupt" fn security_exception_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) {
+    fatal_exception(
+        &stack_frame,
+        30,
+        "SECURITY EXCEPTION",
+        ErrorCode::Code(error_code),
+    );
+}
+
+#[test_case]
+fn guard_pages_sit_below_each_interrupt_stack() {
+    let first_guard_page = 0x_5555_5555_0000;
+    assert_eq!(
+        gdt::ist_guard_page_hit(first_guard_page),
+        Some("double fault")
+    );
+    assert_eq!(gdt::ist_guard_page_hit(first_guard_page + 0x1000), None);
+    assert_eq!(
+        gdt::ist_guard_page_hit(first_guard_page + 0x6000),
+        Some("page fault")
+    );
+}
+
+#[test_case]
+fn selector_error_code_is_decoded() {
+    let error_code = SelectorErrorCode(0x2D); // (5 << 3) | 0b100 | 1: LDT entry 5, external
+    assert_eq!(error_code.table(), "LDT");
+    assert_eq!(error_code.index(), 5);
+    assert!(error_code.external());
+    assert_eq!(SelectorErrorCode(0x12).table(), "IDT");
+}
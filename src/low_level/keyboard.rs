@@ -0,0 +1,278 @@
+//Turns scancodes into key events. Tracks the modifier keys, keeps the keyboard LEDs in sync
+//with the lock keys and maps keys to characters with the layout picked by set_layout().
+use core::sync::atomic::{AtomicU8, Ordering};
+use pc_keyboard::{layouts, HandleControl, KeyboardLayout, ScancodeSet, ScancodeSet1};
+
+pub use pc_keyboard::{DecodedKey, KeyCode, KeyState};
+
+use crate::low_level::{
+    ps2::{self, Ps2Port},
+    sync::IrqSafeMutex,
+};
+
+const LED_SCROLL_LOCK: u8 = 1 << 0;
+const LED_NUM_LOCK: u8 = 1 << 1;
+const LED_CAPS_LOCK: u8 = 1 << 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Layout {
+    Us104,
+    Uk105,
+    De105,
+    Azerty,
+    Dvorak,
+}
+
+pub const LAYOUTS: &[Layout] = &[
+    Layout::Us104,
+    Layout::Uk105,
+    Layout::De105,
+    Layout::Azerty,
+    Layout::Dvorak,
+];
+
+static LAYOUT: AtomicU8 = AtomicU8::new(Layout::Us104 as u8);
+static MODIFIERS: IrqSafeMutex<Modifiers> = IrqSafeMutex::new(Modifiers::new());
+
+impl Layout {
+    pub fn name(self) -> &'static str {
+        match self {
+            Layout::Us104 => "us",
+            Layout::Uk105 => "uk",
+            Layout::De105 => "de",
+            Layout::Azerty => "azerty",
+            Layout::Dvorak => "dvorak",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        LAYOUTS.iter().copied().find(|layout| layout.name() == name)
+    }
+
+    fn map_keycode(self, code: KeyCode, modifiers: &pc_keyboard::Modifiers) -> DecodedKey {
+        let handle_ctrl = HandleControl::Ignore;
+        match self {
+            Layout::Us104 => layouts::Us104Key.map_keycode(code, modifiers, handle_ctrl),
+            Layout::Uk105 => layouts::Uk105Key.map_keycode(code, modifiers, handle_ctrl),
+            Layout::De105 => layouts::De105Key.map_keycode(code, modifiers, handle_ctrl),
+            Layout::Azerty => layouts::Azerty.map_keycode(code, modifiers, handle_ctrl),
+            Layout::Dvorak => layouts::Dvorak104Key.map_keycode(code, modifiers, handle_ctrl),
+        }
+    }
+}
+
+pub fn layout() -> Layout {
+    LAYOUTS[LAYOUT.load(Ordering::Relaxed) as usize]
+}
+
+pub fn set_layout(layout: Layout) {
+    LAYOUT.store(layout as u8, Ordering::Relaxed);
+}
+
+/// Which modifier keys are held down and which lock keys are on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Modifiers {
+    pub left_shift: bool,
+    pub right_shift: bool,
+    pub left_ctrl: bool,
+    pub right_ctrl: bool,
+    pub left_alt: bool,
+    pub alt_gr: bool,
+    pub caps_lock: bool,
+    pub num_lock: bool,
+    pub scroll_lock: bool,
+}
+
+impl Default for Modifiers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Modifiers {
+    pub const fn new() -> Self {
+        Modifiers {
+            left_shift: false,
+            right_shift: false,
+            left_ctrl: false,
+            right_ctrl: false,
+            left_alt: false,
+            alt_gr: false,
+            caps_lock: false,
+            num_lock: false,
+            scroll_lock: false,
+        }
+    }
+
+    pub fn shift(&self) -> bool {
+        self.left_shift || self.right_shift
+    }
+
+    pub fn ctrl(&self) -> bool {
+        self.left_ctrl || self.right_ctrl
+    }
+
+    pub fn alt(&self) -> bool {
+        self.left_alt
+    }
+
+    /// Updates the state for `code` going to `state`. Returns true if a lock key toggled.
+    fn update(&mut self, code: KeyCode, state: KeyState) -> bool {
+        let down = state == KeyState::Down;
+        match code {
+            KeyCode::LShift => self.left_shift = down,
+            KeyCode::RShift => self.right_shift = down,
+            KeyCode::LControl => self.left_ctrl = down,
+            KeyCode::RControl => self.right_ctrl = down,
+            KeyCode::LAlt => self.left_alt = down,
+            KeyCode::RAltGr => self.alt_gr = down,
+            KeyCode::CapsLock if down => {
+                self.caps_lock = !self.caps_lock;
+                return true;
+            }
+            KeyCode::NumpadLock if down => {
+                self.num_lock = !self.num_lock;
+                return true;
+            }
+            KeyCode::ScrollLock if down => {
+                self.scroll_lock = !self.scroll_lock;
+                return true;
+            }
+            _ => {}
+        }
+        false
+    }
+
+    fn leds(&self) -> u8 {
+        let mut leds = 0;
+        if self.scroll_lock {
+            leds |= LED_SCROLL_LOCK;
+        }
+        if self.num_lock {
+            leds |= LED_NUM_LOCK;
+        }
+        if self.caps_lock {
+            leds |= LED_CAPS_LOCK;
+        }
+        leds
+    }
+
+    fn to_pc_keyboard(self) -> pc_keyboard::Modifiers {
+        pc_keyboard::Modifiers {
+            lshift: self.left_shift,
+            rshift: self.right_shift,
+            lctrl: self.left_ctrl,
+            rctrl: self.right_ctrl,
+            numlock: self.num_lock,
+            capslock: self.caps_lock,
+            alt_gr: self.alt_gr,
+            rctrl2: false,
+        }
+    }
+}
+
+/// The current modifier state.
+pub fn modifiers() -> Modifiers {
+    *MODIFIERS.lock()
+}
+
+/// Whether `code` is a modifier or lock key, which don't type anything on their own.
+pub fn is_modifier(code: KeyCode) -> bool {
+    matches!(
+        code,
+        KeyCode::LShift
+            | KeyCode::RShift
+            | KeyCode::LControl
+            | KeyCode::RControl
+            | KeyCode::LAlt
+            | KeyCode::RAltGr
+            | KeyCode::CapsLock
+            | KeyCode::NumpadLock
+            | KeyCode::ScrollLock
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub code: KeyCode,
+    pub state: KeyState,
+    /// What the key means in the current layout. Only set for key presses.
+    pub key: Option<DecodedKey>,
+    /// The modifiers after this event was applied.
+    pub modifiers: Modifiers,
+}
+
+/// Decodes the bytes the keyboard sends, which arrive translated to scancode set 1.
+pub struct KeyboardDecoder {
+    scancodes: ScancodeSet1,
+    /// The LED state to send once the keyboard acknowledged the set LEDs command.
+    pending_leds: Option<u8>,
+}
+
+impl KeyboardDecoder {
+    pub fn new() -> Self {
+        KeyboardDecoder {
+            scancodes: ScancodeSet1::new(),
+            pending_leds: None,
+        }
+    }
+
+    pub fn add_byte(&mut self, byte: u8) -> Option<KeyEvent> {
+        match byte {
+            ps2::ACK => {
+                if let Some(leds) = self.pending_leds.take() {
+                    let _ = ps2::write_device(Ps2Port::First, leds);
+                }
+                return None;
+            }
+            ps2::RESEND => return None,
+            _ => {}
+        }
+
+        let event = self.scancodes.advance_state(byte).ok()??;
+        let mut modifiers = MODIFIERS.lock();
+        if modifiers.update(event.code, event.state) {
+            self.pending_leds = Some(modifiers.leds());
+            let _ = ps2::write_device(Ps2Port::First, ps2::KEYBOARD_SET_LEDS);
+        }
+        let key = (event.state == KeyState::Down)
+            .then(|| layout().map_keycode(event.code, &modifiers.to_pc_keyboard()));
+        Some(KeyEvent {
+            code: event.code,
+            state: event.state,
+            key,
+            modifiers: *modifiers,
+        })
+    }
+}
+
+impl Default for KeyboardDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test_case]
+fn lock_keys_toggle_on_press_only() {
+    let mut modifiers = Modifiers::new();
+    assert!(modifiers.update(KeyCode::CapsLock, KeyState::Down));
+    assert!(!modifiers.update(KeyCode::CapsLock, KeyState::Up));
+    assert!(!modifiers.update(KeyCode::LShift, KeyState::Down));
+    assert!(modifiers.caps_lock && modifiers.shift());
+    assert_eq!(modifiers.leds(), LED_CAPS_LOCK);
+}
+
+#[test_case]
+fn layouts_map_keys_differently() {
+    let modifiers = Modifiers::new().to_pc_keyboard();
+    assert_eq!(
+        Layout::Us104.map_keycode(KeyCode::Y, &modifiers),
+        DecodedKey::Unicode('y')
+    );
+    assert_eq!(
+        Layout::De105.map_keycode(KeyCode::Y, &modifiers),
+        DecodedKey::Unicode('z')
+    );
+    assert_eq!(Layout::from_name("azerty"), Some(Layout::Azerty));
+}
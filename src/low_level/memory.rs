@@ -1,9 +1,58 @@
 use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use core::sync::atomic::{AtomicU64, Ordering};
 use x86_64::{
-    structures::paging::{FrameAllocator, OffsetPageTable, PageTable, PhysFrame, Size4KiB},
+    structures::paging::{
+        mapper::MapToError, FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page,
+        PageSize, PageTable, PageTableFlags, PhysFrame, Size2MiB, Size4KiB,
+    },
     PhysAddr, VirtAddr,
 };
 
+use crate::low_level::sync::IrqSafeMutex;
+
+/// The kernel's page table mapper and frame allocator, available once `crate::init` is done.
+/// Taken by the heap while it grows, so always after the heap lock, never before it.
+pub static MEMORY: IrqSafeMutex<Option<KernelMemory>> = IrqSafeMutex::new(None);
+
+pub struct KernelMemory {
+    pub mapper: OffsetPageTable<'static>,
+    pub frame_allocator: PopFrameAllocator,
+}
+
+const MMIO_START: u64 = 0x_6666_6666_0000;
+static NEXT_MMIO_ADDRESS: AtomicU64 = AtomicU64::new(MMIO_START);
+
+impl KernelMemory {
+    /// Maps `size` bytes of device registers at `physical_address` as uncached memory and
+    /// returns the virtual address they can be accessed at.
+    pub fn map_mmio(
+        &mut self,
+        physical_address: PhysAddr,
+        size: u64,
+    ) -> Result<VirtAddr, MapToError<Size4KiB>> {
+        let first_frame = PhysFrame::containing_address(physical_address);
+        let last_frame = PhysFrame::containing_address(physical_address + size.max(1) - 1u64);
+        let frames = PhysFrame::range_inclusive(first_frame, last_frame);
+        let start =
+            NEXT_MMIO_ADDRESS.fetch_add(frames.count() as u64 * FRAME_SIZE, Ordering::Relaxed);
+        let first_page = Page::<Size4KiB>::containing_address(VirtAddr::new(start));
+
+        let flags = PageTableFlags::PRESENT
+            | PageTableFlags::WRITABLE
+            | PageTableFlags::NO_CACHE
+            | PageTableFlags::WRITE_THROUGH;
+        for (index, frame) in frames.enumerate() {
+            let page = first_page + index as u64;
+            unsafe {
+                self.mapper
+                    .map_to(page, frame, flags, &mut self.frame_allocator)?
+                    .flush()
+            };
+        }
+        Ok(first_page.start_address() + physical_address.as_u64() % FRAME_SIZE)
+    }
+}
+
 pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
     let level_4_table = active_level_4_table(physical_memory_offset);
     OffsetPageTable::new(level_4_table, physical_memory_offset)
@@ -21,37 +70,202 @@ unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut
     &mut *page_table_ptr // unsafe
 }
 
+const FRAME_SIZE: u64 = Size4KiB::SIZE;
+const FRAMES_PER_HUGE_FRAME: usize = (Size2MiB::SIZE / Size4KiB::SIZE) as usize;
+const BITS_PER_WORD: usize = u64::BITS as usize;
+
+/// Physical frame allocator backed by a bitmap with one bit per 4 KiB frame.
+///
+/// A set bit means the frame is in use (or was never usable in the first place).
+/// The bitmap itself lives in the first usable region that is large enough to hold it
+/// and is accessed through the physical memory mapping set up by the bootloader.
 pub struct PopFrameAllocator {
-    memory_map: &'static MemoryMap,
+    bitmap: &'static mut [u64],
+    frame_count: usize,
+    usable_frames: usize,
+    free_frames: usize,
     next: usize,
 }
 
 impl PopFrameAllocator {
-    pub unsafe fn init(memory_map: &'static MemoryMap) -> Self {
-        PopFrameAllocator {
-            memory_map,
+    pub unsafe fn init(memory_map: &'static MemoryMap, physical_memory_offset: VirtAddr) -> Self {
+        let usable_regions = || {
+            memory_map
+                .iter()
+                .filter(|r| r.region_type == MemoryRegionType::Usable)
+        };
+
+        let memory_end = usable_regions()
+            .map(|r| r.range.end_addr())
+            .max()
+            .unwrap_or(0);
+        let frame_count = (memory_end / FRAME_SIZE) as usize;
+        let word_count = frame_count.div_ceil(BITS_PER_WORD);
+        let bitmap_size = (word_count * core::mem::size_of::<u64>()) as u64;
+
+        let bitmap_start = usable_regions()
+            .find(|r| r.range.end_addr() - r.range.start_addr() >= bitmap_size)
+            .map(|r| r.range.start_addr())
+            .expect("no usable memory region is large enough for the frame bitmap");
+        let bitmap_ptr: *mut u64 = (physical_memory_offset + bitmap_start).as_mut_ptr();
+        let bitmap = core::slice::from_raw_parts_mut(bitmap_ptr, word_count);
+        bitmap.fill(u64::MAX);
+
+        let mut allocator = PopFrameAllocator {
+            bitmap,
+            frame_count,
+            usable_frames: 0,
+            free_frames: 0,
             next: 0,
+        };
+        for region in usable_regions() {
+            let start = region.range.start_frame_number as usize;
+            let end = region.range.end_frame_number as usize;
+            allocator.mark_range(start, end - start, false);
+            allocator.usable_frames += end - start;
+        }
+        let bitmap_frames = bitmap_size.div_ceil(FRAME_SIZE) as usize;
+        allocator.mark_range((bitmap_start / FRAME_SIZE) as usize, bitmap_frames, true);
+        allocator
+    }
+
+    /// Number of frames that were reported usable by the bootloader.
+    pub fn total_frames(&self) -> usize {
+        self.usable_frames
+    }
+
+    pub fn free_frames(&self) -> usize {
+        self.free_frames
+    }
+
+    pub fn used_frames(&self) -> usize {
+        self.usable_frames.saturating_sub(self.free_frames)
+    }
+
+    /// Allocates `count` physically contiguous 4 KiB frames, with the first frame
+    /// aligned to `align` frames. Returns the first frame of the run.
+    pub fn allocate_contiguous(&mut self, count: usize, align: usize) -> Option<PhysFrame> {
+        if count == 0 || align == 0 {
+            return None;
+        }
+        let mut start = 0;
+        while start + count <= self.frame_count {
+            match (start..start + count)
+                .rev()
+                .find(|&frame| self.is_used(frame))
+            {
+                Some(used) => start = (used + 1).next_multiple_of(align),
+                None => {
+                    self.mark_range(start, count, true);
+                    return Some(Self::frame_at(start));
+                }
+            }
         }
+        None
     }
 
-    /// Returns an iterator over the usable frames specified in the memory map.
-    fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
-        // get usable regions from memory map
-        let regions = self.memory_map.iter();
-        let usable_regions = regions.filter(|r| r.region_type == MemoryRegionType::Usable);
-        // map each region to its address range
-        let addr_ranges = usable_regions.map(|r| r.range.start_addr()..r.range.end_addr());
-        // transform to an iterator of frame start addresses
-        let frame_addresses = addr_ranges.flat_map(|r| r.step_by(4096));
-        // create `PhysFrame` types from the start addresses
-        frame_addresses.map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
+    /// Returns `count` frames starting at `frame` to the allocator.
+    ///
+    /// # Safety
+    /// The frames must have been allocated by this allocator and must no longer be in use.
+    pub unsafe fn deallocate_contiguous(&mut self, frame: PhysFrame, count: usize) {
+        let start = (frame.start_address().as_u64() / FRAME_SIZE) as usize;
+        self.mark_range(start, count, false);
+    }
+
+    fn frame_at(index: usize) -> PhysFrame {
+        PhysFrame::containing_address(PhysAddr::new(index as u64 * FRAME_SIZE))
+    }
+
+    fn is_used(&self, frame: usize) -> bool {
+        self.bitmap[frame / BITS_PER_WORD] & (1 << (frame % BITS_PER_WORD)) != 0
+    }
+
+    fn mark_range(&mut self, start: usize, count: usize, used: bool) {
+        let end = (start + count).min(self.frame_count);
+        for frame in start..end {
+            if self.is_used(frame) == used {
+                debug_assert!(used, "frame {:#x} freed twice", frame as u64 * FRAME_SIZE);
+                continue;
+            }
+            let word = &mut self.bitmap[frame / BITS_PER_WORD];
+            *word ^= 1 << (frame % BITS_PER_WORD);
+            if used {
+                self.free_frames -= 1;
+            } else {
+                self.free_frames += 1;
+                self.next = self.next.min(frame / BITS_PER_WORD);
+            }
+        }
+    }
+
+    fn find_free_frame(&self) -> Option<usize> {
+        let words = self.bitmap.len();
+        (0..words)
+            .map(|offset| (self.next + offset) % words)
+            .find(|&word| self.bitmap[word] != u64::MAX)
+            .map(|word| word * BITS_PER_WORD + self.bitmap[word].trailing_ones() as usize)
+            .filter(|&frame| frame < self.frame_count)
     }
 }
 
 unsafe impl FrameAllocator<Size4KiB> for PopFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        let frame = self.usable_frames().nth(self.next);
-        self.next += 1;
-        frame
+        let frame = self.find_free_frame()?;
+        self.mark_range(frame, 1, true);
+        self.next = frame / BITS_PER_WORD;
+        Some(Self::frame_at(frame))
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for PopFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        self.deallocate_contiguous(frame, 1);
+    }
+}
+
+unsafe impl FrameAllocator<Size2MiB> for PopFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size2MiB>> {
+        let frame = self.allocate_contiguous(FRAMES_PER_HUGE_FRAME, FRAMES_PER_HUGE_FRAME)?;
+        PhysFrame::from_start_address(frame.start_address()).ok()
+    }
+}
+
+impl FrameDeallocator<Size2MiB> for PopFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size2MiB>) {
+        let first = PhysFrame::containing_address(frame.start_address());
+        self.deallocate_contiguous(first, FRAMES_PER_HUGE_FRAME);
     }
 }
+
+#[test_case]
+fn frames_are_reused_after_deallocation() {
+    let mut memory = MEMORY.lock();
+    let frame_allocator = &mut memory.as_mut().unwrap().frame_allocator;
+    let free_frames = frame_allocator.free_frames();
+
+    let frame: PhysFrame = frame_allocator.allocate_frame().unwrap();
+    assert_eq!(frame_allocator.free_frames(), free_frames - 1);
+    unsafe { frame_allocator.deallocate_frame(frame) };
+    assert_eq!(frame_allocator.free_frames(), free_frames);
+
+    let reused: PhysFrame = frame_allocator.allocate_frame().unwrap();
+    assert_eq!(reused, frame);
+    unsafe { frame_allocator.deallocate_frame(reused) };
+}
+
+#[test_case]
+fn huge_frames_are_contiguous_and_aligned() {
+    let mut memory = MEMORY.lock();
+    let frame_allocator = &mut memory.as_mut().unwrap().frame_allocator;
+    let free_frames = frame_allocator.free_frames();
+
+    let frame: PhysFrame<Size2MiB> = frame_allocator.allocate_frame().unwrap();
+    assert!(frame.start_address().is_aligned(Size2MiB::SIZE));
+    assert_eq!(
+        frame_allocator.free_frames(),
+        free_frames - FRAMES_PER_HUGE_FRAME
+    );
+    unsafe { frame_allocator.deallocate_frame(frame) };
+    assert_eq!(frame_allocator.free_frames(), free_frames);
+}
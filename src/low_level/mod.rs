@@ -1,5 +1,17 @@
+pub mod acpi;
 pub mod allocator;
+pub mod apic;
 pub mod gdt;
 pub mod interrupts;
+pub mod keyboard;
 pub mod memory;
+pub mod mouse;
+pub mod panic_screen;
+pub mod power;
+pub mod ps2;
+pub mod scheduler;
+pub mod serial;
+pub mod sync;
+pub mod task;
+pub mod timer;
 pub mod vga_buffer;
@@ -0,0 +1,175 @@
+//Driver for the mouse on the second PS/2 port. init() switches it to IntelliMouse mode if it
+//supports that, which adds a fourth byte with the scroll wheel to every packet. The mouse
+//interrupt hands each byte to add_byte(), which assembles the packets and queues the finished
+//events for `task::mouse`.
+use crate::low_level::{
+    interrupts::{self, InterruptIndex},
+    ps2::{self, Ps2Error, Ps2Port},
+    sync::IrqSafeMutex,
+    task,
+};
+use spin::Once;
+
+const SET_DEFAULTS: u8 = 0xF6;
+const SET_SAMPLE_RATE: u8 = 0xF3;
+const GET_DEVICE_ID: u8 = 0xF2;
+const ENABLE_DATA_REPORTING: u8 = 0xF4;
+
+/// Setting these sample rates in a row is the knock that unlocks the scroll wheel.
+const INTELLIMOUSE_SAMPLE_RATES: [u8; 3] = [200, 100, 80];
+const INTELLIMOUSE_ID: u8 = 3;
+const SAMPLE_RATE: u8 = 100;
+
+const BUTTON_LEFT: u8 = 1 << 0;
+const BUTTON_RIGHT: u8 = 1 << 1;
+const BUTTON_MIDDLE: u8 = 1 << 2;
+const ALWAYS_SET: u8 = 1 << 3;
+const X_SIGN: u8 = 1 << 4;
+const Y_SIGN: u8 = 1 << 5;
+const X_OVERFLOW: u8 = 1 << 6;
+const Y_OVERFLOW: u8 = 1 << 7;
+
+static MOUSE: Once<Mouse> = Once::new();
+static DECODER: IrqSafeMutex<PacketDecoder> = IrqSafeMutex::new(PacketDecoder::new(false));
+
+/// What `init` found.
+#[derive(Debug, Clone, Copy)]
+pub struct Mouse {
+    pub has_scroll_wheel: bool,
+}
+
+pub fn mouse() -> Option<&'static Mouse> {
+    MOUSE.get()
+}
+
+/// Sets up the device on the second PS/2 port as a mouse and lets it report. Has to run
+/// after `ps2::init` found a device there and before interrupts are enabled.
+pub fn init() -> Result<&'static Mouse, Ps2Error> {
+    if let Some(mouse) = MOUSE.get() {
+        return Ok(mouse);
+    }
+
+    ps2::device_command(Ps2Port::Second, SET_DEFAULTS)?;
+    for rate in INTELLIMOUSE_SAMPLE_RATES {
+        set_sample_rate(rate)?;
+    }
+    ps2::device_command(Ps2Port::Second, GET_DEVICE_ID)?;
+    let has_scroll_wheel = ps2::read_data(ps2::TIMEOUT)? == INTELLIMOUSE_ID;
+    set_sample_rate(SAMPLE_RATE)?;
+
+    *DECODER.lock() = PacketDecoder::new(has_scroll_wheel);
+    ps2::device_command(Ps2Port::Second, ENABLE_DATA_REPORTING)?;
+    interrupts::unmask_pic_interrupt(InterruptIndex::Mouse);
+
+    Ok(MOUSE.call_once(|| Mouse { has_scroll_wheel }))
+}
+
+fn set_sample_rate(rate: u8) -> Result<(), Ps2Error> {
+    ps2::device_command(Ps2Port::Second, SET_SAMPLE_RATE)?;
+    ps2::device_command(Ps2Port::Second, rate)
+}
+
+/// Called by the mouse interrupt handler with every byte the mouse sends.
+pub(crate) fn add_byte(byte: u8) {
+    if let Some(event) = DECODER.lock().add_byte(byte) {
+        task::mouse::add_event(event);
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MouseButtons {
+    pub left: bool,
+    pub right: bool,
+    pub middle: bool,
+}
+
+/// One packet's worth of movement, relative to the previous one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MouseEvent {
+    /// Positive is to the right.
+    pub dx: i16,
+    /// Positive is down, like rows on the screen. The mouse itself counts up.
+    pub dy: i16,
+    /// Positive is towards the user. Always 0 without a scroll wheel.
+    pub scroll: i8,
+    /// Which buttons are held down after this event.
+    pub buttons: MouseButtons,
+}
+
+/// Assembles the 3 byte packets of a standard mouse, or the 4 byte ones of an IntelliMouse.
+pub struct PacketDecoder {
+    packet: [u8; 4],
+    received: usize,
+    packet_size: usize,
+}
+
+impl PacketDecoder {
+    pub const fn new(has_scroll_wheel: bool) -> Self {
+        PacketDecoder {
+            packet: [0; 4],
+            received: 0,
+            packet_size: if has_scroll_wheel { 4 } else { 3 },
+        }
+    }
+
+    pub fn add_byte(&mut self, byte: u8) -> Option<MouseEvent> {
+        // a first byte without this bit means we lost track of where packets start,
+        // dropping bytes until one has it gets us back in step
+        if self.received == 0 && byte & ALWAYS_SET == 0 {
+            return None;
+        }
+        self.packet[self.received] = byte;
+        self.received += 1;
+        if self.received < self.packet_size {
+            return None;
+        }
+        self.received = 0;
+        Some(self.decode())
+    }
+
+    fn decode(&self) -> MouseEvent {
+        let [flags, x, y, z] = self.packet;
+        // the values are 9 bit two's complement, with the sign bit in the first byte
+        let movement = |value: u8, sign: u8, overflow: u8| {
+            if flags & overflow != 0 {
+                0
+            } else if flags & sign != 0 {
+                i16::from(value) - 0x100
+            } else {
+                i16::from(value)
+            }
+        };
+        MouseEvent {
+            dx: movement(x, X_SIGN, X_OVERFLOW),
+            dy: -movement(y, Y_SIGN, Y_OVERFLOW),
+            scroll: if self.packet_size == 4 { z as i8 } else { 0 },
+            buttons: MouseButtons {
+                left: flags & BUTTON_LEFT != 0,
+                right: flags & BUTTON_RIGHT != 0,
+                middle: flags & BUTTON_MIDDLE != 0,
+            },
+        }
+    }
+}
+
+#[test_case]
+fn decodes_standard_packets() {
+    let mut decoder = PacketDecoder::new(false);
+    assert_eq!(decoder.add_byte(ALWAYS_SET | X_SIGN | BUTTON_LEFT), None);
+    assert_eq!(decoder.add_byte(0xFE), None);
+    let event = decoder.add_byte(5).unwrap();
+    assert_eq!((event.dx, event.dy, event.scroll), (-2, -5, 0));
+    assert!(event.buttons.left && !event.buttons.right);
+}
+
+#[test_case]
+fn decodes_scroll_wheel_and_resynchronizes() {
+    let mut decoder = PacketDecoder::new(true);
+    // stray bytes without the always set bit are skipped
+    assert_eq!(decoder.add_byte(0x00), None);
+    for byte in [ALWAYS_SET | Y_SIGN | Y_OVERFLOW, 1, 0x80] {
+        assert_eq!(decoder.add_byte(byte), None);
+    }
+    let event = decoder.add_byte(0xFF).unwrap();
+    assert_eq!((event.dx, event.dy, event.scroll), (1, 0, -1));
+}
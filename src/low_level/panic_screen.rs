@@ -0,0 +1,256 @@
+use core::{
+    arch::asm,
+    fmt::{self, Write},
+    panic::PanicInfo,
+};
+use spin::Mutex;
+use x86_64::{
+    instructions::{
+        interrupts,
+        port::Port,
+        segmentation::{Segment, CS, SS},
+    },
+    registers::{control::Cr2, rflags},
+    structures::idt::{InterruptStackFrame, PageFaultErrorCode},
+};
+
+use crate::{
+    low_level::{
+        interrupts::SelectorErrorCode,
+        power,
+        serial::SERIAL1,
+        vga_buffer::{
+            buffer::{to_code_page_437, Buffer, Char, ColorCode, BUFFER_HEIGHT, BUFFER_WIDTH},
+            cursor, Color, VGA_BUFFER,
+        },
+    },
+    userspace::locale,
+};
+
+const FOREGROUND: Color = Color::White;
+const BACKGROUND: Color = Color::Blue;
+const KEYBOARD_DATA: u16 = 0x60;
+const KEYBOARD_STATUS: u16 = 0x64;
+const KEYBOARD_OUTPUT_FULL: u8 = 1 << 0;
+const SCANCODE_R_PRESSED: u8 = 0x13;
+
+/// The CPU state at the last exception that ended in a panic, filled in by the exception
+/// handlers right before they call `panic!`.
+static LAST_EXCEPTION: Mutex<Option<ExceptionContext>> = Mutex::new(None);
+
+#[derive(Clone, Copy)]
+pub enum ErrorCode {
+    None,
+    Code(u64),
+    Selector(SelectorErrorCode),
+    PageFault(PageFaultErrorCode),
+}
+
+impl fmt::Debug for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorCode::None => write!(f, "NONE"),
+            ErrorCode::Code(code) => write!(f, "{:#x}", code),
+            ErrorCode::Selector(code) => write!(f, "{:?}", code),
+            ErrorCode::PageFault(code) => write!(f, "{:?}", code),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ExceptionContext {
+    instruction_pointer: u64,
+    code_segment: u64,
+    cpu_flags: u64,
+    stack_pointer: u64,
+    stack_segment: u64,
+    accessed_address: u64,
+    error_code: ErrorCode,
+}
+
+impl ExceptionContext {
+    /// Used for panics that did not come from an exception. There is no faulting
+    /// instruction, so the registers are the ones of the panic handler itself.
+    fn current() -> Self {
+        let stack_pointer: u64;
+        unsafe { asm!("mov {}, rsp", out(reg) stack_pointer) };
+        ExceptionContext {
+            instruction_pointer: 0,
+            code_segment: CS::get_reg().0.into(),
+            cpu_flags: rflags::read_raw(),
+            stack_pointer,
+            stack_segment: SS::get_reg().0.into(),
+            accessed_address: Cr2::read_raw(),
+            error_code: ErrorCode::None,
+        }
+    }
+}
+
+/// Remembers the state of an exception so the panic screen can show it.
+pub fn capture_exception(stack_frame: &InterruptStackFrame, error_code: ErrorCode) {
+    let context = ExceptionContext {
+        instruction_pointer: stack_frame.instruction_pointer.as_u64(),
+        code_segment: stack_frame.code_segment,
+        cpu_flags: stack_frame.cpu_flags,
+        stack_pointer: stack_frame.stack_pointer.as_u64(),
+        stack_segment: stack_frame.stack_segment,
+        accessed_address: Cr2::read_raw(),
+        error_code,
+    };
+    // a nested exception may have happened while the lock was held
+    unsafe { LAST_EXCEPTION.force_unlock() };
+    *LAST_EXCEPTION.lock() = Some(context);
+}
+
+/// Draws the panic report over the whole screen, copies it to the serial port and waits
+/// for R to be pressed to reboot.
+pub fn show(info: &PanicInfo) -> ! {
+    interrupts::disable();
+    let context = LAST_EXCEPTION
+        .try_lock()
+        .and_then(|exception| *exception)
+        .unwrap_or_else(ExceptionContext::current);
+    let report = PanicReport { info, context };
+
+    let mut screen = ScreenWriter::new();
+    screen.clear();
+    cursor::disable();
+    let _ = report.render(&mut screen);
+    if let Some(mut serial) = SERIAL1.try_lock() {
+        let _ = report.render(&mut *serial);
+    }
+    screen.row = BUFFER_HEIGHT - 1;
+    screen.column = 0;
+    let _ = screen.write_str(locale::message("panic.reboot_hint"));
+    wait_for_reboot_key();
+}
+
+/// Polls the keyboard, since interrupts stay off after a panic.
+fn wait_for_reboot_key() -> ! {
+    let mut status = Port::<u8>::new(KEYBOARD_STATUS);
+    let mut data = Port::<u8>::new(KEYBOARD_DATA);
+    loop {
+        unsafe {
+            if status.read() & KEYBOARD_OUTPUT_FULL != 0 && data.read() == SCANCODE_R_PRESSED {
+                power::reboot();
+            }
+        }
+        core::hint::spin_loop();
+    }
+}
+
+struct PanicReport<'a> {
+    info: &'a PanicInfo<'a>,
+    context: ExceptionContext,
+}
+
+impl PanicReport<'_> {
+    /// Copies the template to `writer`, replacing every `{index}` / `{index:spec}`
+    /// placeholder with the matching field. The only specs understood are `?` and
+    /// zero padded hex like `0>16x`, which is all the templates use.
+    fn render(&self, writer: &mut impl Write) -> fmt::Result {
+        let mut rest = locale::panic_template();
+        while let Some(start) = rest.find('{') {
+            writer.write_str(&rest[..start])?;
+            let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+                rest = &rest[start..];
+                break;
+            };
+            let placeholder = &rest[start + 1..end];
+            let (index, spec) = placeholder.split_once(':').unwrap_or((placeholder, ""));
+            match index.parse() {
+                Ok(index) => self.write_field(writer, index, spec)?,
+                Err(_) => writer.write_str(&rest[start..=end])?,
+            }
+            rest = &rest[end + 1..];
+        }
+        writer.write_str(rest)
+    }
+
+    fn write_field(&self, writer: &mut impl Write, index: usize, spec: &str) -> fmt::Result {
+        let context = &self.context;
+        let register = match index {
+            0 => {
+                return match self.info.location() {
+                    Some(location) => write!(writer, "{}", location),
+                    None => write!(writer, "unknown"),
+                }
+            }
+            1 => return write!(writer, "{}", self.info.message()),
+            2 => context.instruction_pointer,
+            3 => context.code_segment,
+            4 => context.cpu_flags,
+            5 => context.stack_pointer,
+            6 => context.stack_segment,
+            7 => context.accessed_address,
+            8 => return write!(writer, "{:?}", context.error_code),
+            _ => return Ok(()),
+        };
+        let width = spec
+            .strip_prefix("0>")
+            .and_then(|spec| spec.strip_suffix('x'))
+            .and_then(|width| width.parse().ok());
+        match width {
+            Some(width) => write!(writer, "{:0width$x}", register, width = width),
+            None if spec.ends_with('x') => write!(writer, "{:x}", register),
+            None => write!(writer, "{}", register),
+        }
+    }
+}
+
+/// Writes straight into the VGA buffer, so the panic screen works even if the panic
+/// happened while `WRITER` was locked.
+struct ScreenWriter {
+    row: usize,
+    column: usize,
+    color_code: ColorCode,
+    buffer: &'static mut Buffer,
+}
+
+impl ScreenWriter {
+    fn new() -> Self {
+        ScreenWriter {
+            row: 1,
+            column: 0,
+            color_code: ColorCode::new(FOREGROUND, BACKGROUND),
+            buffer: unsafe { &mut *(VGA_BUFFER as *mut Buffer) },
+        }
+    }
+
+    fn clear(&mut self) {
+        let blank = Char {
+            ascii_character: b' ',
+            color_code: self.color_code,
+        };
+        for row in self.buffer.chars.iter_mut() {
+            row.fill(blank);
+        }
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        if byte == b'\n' || self.column >= BUFFER_WIDTH {
+            self.row += 1;
+            self.column = 0;
+            if byte == b'\n' {
+                return;
+            }
+        }
+        if self.row >= BUFFER_HEIGHT {
+            return;
+        }
+        self.buffer.chars[self.row][self.column] = Char {
+            ascii_character: byte,
+            color_code: self.color_code,
+        };
+        self.column += 1;
+    }
+}
+
+impl Write for ScreenWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for character in s.chars() {
+            self.write_byte(to_code_page_437(character));
+        }
+        Ok(())
+    }
+}
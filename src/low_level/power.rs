@@ -0,0 +1,156 @@
+//Turning the machine off and restarting it. Both try ACPI first, using the FADT found by
+//acpi::init, and fall back to the legacy ways that work on older hardware and emulators.
+use x86_64::instructions::{interrupts, port::Port};
+
+use crate::{
+    hlt_loop,
+    low_level::acpi::{self, AddressSpace, Sdt},
+};
+
+const SLEEP_TYPE_SHIFT: u16 = 10;
+const SLEEP_TYPE_MASK: u16 = 0b111 << SLEEP_TYPE_SHIFT;
+const SLEEP_ENABLE: u16 = 1 << 13;
+const SCI_ENABLE: u16 = 1 << 0;
+const POLL_TIMEOUT: usize = 1_000_000;
+
+const AML_NAME_OP: u8 = 0x08;
+const AML_PACKAGE_OP: u8 = 0x12;
+const AML_BYTE_PREFIX: u8 = 0x0A;
+
+const KEYBOARD_CONTROLLER_STATUS: u16 = 0x64;
+const KEYBOARD_CONTROLLER_INPUT_FULL: u8 = 1 << 1;
+const KEYBOARD_CONTROLLER_RESET: u8 = 0xFE;
+
+/// Newer QEMU machines shut down when 0x2000 is written here, Bochs and older QEMU use 0xB004.
+const EMULATOR_SHUTDOWN_PORTS: [u16; 2] = [0x604, 0xB004];
+const EMULATOR_SHUTDOWN_VALUE: u16 = 0x2000;
+
+/// Turns the machine off by entering the ACPI S5 state. Halts if nothing worked.
+pub fn shutdown() -> ! {
+    interrupts::disable();
+    unsafe {
+        acpi_shutdown();
+        for port in EMULATOR_SHUTDOWN_PORTS {
+            Port::new(port).write(EMULATOR_SHUTDOWN_VALUE);
+        }
+    }
+    hlt_loop();
+}
+
+/// Restarts the machine through the ACPI reset register, then the keyboard controller,
+/// and as a last resort by triple faulting.
+pub fn reboot() -> ! {
+    interrupts::disable();
+    unsafe {
+        acpi_reset();
+        keyboard_controller_reset();
+        triple_fault();
+    }
+}
+
+unsafe fn acpi_shutdown() -> Option<()> {
+    let tables = acpi::tables()?;
+    let fadt = tables.fadt.as_ref()?;
+    let dsdt = Sdt::at(tables.physical_memory_offset, fadt.dsdt).ok()?;
+    let (sleep_type_a, sleep_type_b) = s5_sleep_types(dsdt.body())?;
+    let pm1a_control_block = fadt.pm1a_control_block?;
+
+    let mut pm1a_control = Port::<u16>::new(pm1a_control_block);
+    if pm1a_control.read() & SCI_ENABLE == 0 {
+        let smi_command_port = fadt.smi_command_port?;
+        Port::<u8>::new(smi_command_port).write(fadt.acpi_enable);
+        (0..POLL_TIMEOUT).find(|_| pm1a_control.read() & SCI_ENABLE != 0)?;
+    }
+
+    enter_sleep_state(pm1a_control, sleep_type_a);
+    if let Some(pm1b_control_block) = fadt.pm1b_control_block {
+        enter_sleep_state(Port::new(pm1b_control_block), sleep_type_b);
+    }
+    Some(())
+}
+
+/// Sets SLP_TYP and SLP_EN in a PM1 control register, keeping its other bits as the
+/// ACPI spec asks.
+unsafe fn enter_sleep_state(mut control: Port<u16>, sleep_type: u8) {
+    let preserved = control.read() & !(SLEEP_TYPE_MASK | SLEEP_ENABLE);
+    let sleep_type = (u16::from(sleep_type) << SLEEP_TYPE_SHIFT) & SLEEP_TYPE_MASK;
+    control.write(preserved | sleep_type | SLEEP_ENABLE);
+}
+
+unsafe fn acpi_reset() -> Option<()> {
+    let fadt = acpi::tables()?.fadt.as_ref()?;
+    let reset_register = fadt.reset_register?;
+    // memory and PCI reset registers would need mapping or PCI access, which we don't have
+    if reset_register.address_space == AddressSpace::SystemIo {
+        let port = u16::try_from(reset_register.address).ok()?;
+        Port::<u8>::new(port).write(fadt.reset_value);
+    }
+    Some(())
+}
+
+/// Pulses the CPU reset line through the 8042 keyboard controller.
+unsafe fn keyboard_controller_reset() {
+    let mut controller = Port::<u8>::new(KEYBOARD_CONTROLLER_STATUS);
+    for _ in 0..POLL_TIMEOUT {
+        if controller.read() & KEYBOARD_CONTROLLER_INPUT_FULL == 0 {
+            break;
+        }
+    }
+    controller.write(KEYBOARD_CONTROLLER_RESET);
+}
+
+/// Loads an empty IDT and raises an exception, which the CPU can only answer with a reset.
+unsafe fn triple_fault() -> ! {
+    use x86_64::{instructions::tables::lidt, structures::DescriptorTablePointer, VirtAddr};
+
+    let empty_idt = DescriptorTablePointer {
+        limit: 0,
+        base: VirtAddr::new(0),
+    };
+    lidt(&empty_idt);
+    x86_64::instructions::interrupts::int3();
+    hlt_loop();
+}
+
+/// Finds the `\_S5_` package in the AML of the DSDT and returns its first two values, the
+/// SLP_TYPa and SLP_TYPb that put the machine into soft off.
+fn s5_sleep_types(aml: &[u8]) -> Option<(u8, u8)> {
+    let position = aml.windows(4).position(|window| window == b"_S5_")?;
+    let is_definition = match position {
+        0 => false,
+        _ if aml[position - 1] == AML_NAME_OP => true,
+        1 => false,
+        _ => aml[position - 1] == b'\\' && aml[position - 2] == AML_NAME_OP,
+    };
+    let mut bytes = aml[position + 4..].iter().copied();
+    if !is_definition || bytes.next()? != AML_PACKAGE_OP {
+        return None;
+    }
+    // the package length encodes how many more length bytes follow in its top two bits
+    let package_length = bytes.next()?;
+    bytes.nth(usize::from(package_length >> 6))?; // skip those and the element count
+
+    let mut next_value = || match bytes.next()? {
+        AML_BYTE_PREFIX => bytes.next(),
+        value => Some(value),
+    };
+    Some((next_value()?, next_value()?))
+}
+
+#[test_case]
+fn finds_s5_sleep_types() {
+    let aml = [
+        0x08, b'_', b'S', b'5', b'_', 0x12, 0x06, 0x04, 0x0A, 0x05, 0x00, 0x00, 0x00,
+    ];
+    assert_eq!(s5_sleep_types(&aml), Some((5, 0)));
+    let qemu_aml = [
+        0x08, b'\\', b'_', b'S', b'5', b'_', 0x12, 0x06, 0x04, 0x00, 0x0A, 0x07, 0x00, 0x00,
+    ];
+    assert_eq!(s5_sleep_types(&qemu_aml), Some((0, 7)));
+}
+
+#[test_case]
+fn ignores_references_to_s5() {
+    assert_eq!(s5_sleep_types(b"\x70_S5_\x12\x06\x04\x0A\x05"), None);
+    assert_eq!(s5_sleep_types(b"\x08_S5_"), None);
+}
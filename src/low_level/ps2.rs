@@ -0,0 +1,237 @@
+//Driver for the 8042 PS/2 controller. init() resets it into a known state, finds out which of
+//its two ports have working devices and sets the keyboard up. Afterwards the keyboard talks
+//through IRQ 1 and, if there is one, the device on the second port (usually a mouse) through IRQ 12.
+//device_command() waits for the device's answer by polling, so it only works while the
+//interrupt handlers don't read the data port, that is before interrupts are enabled. Later,
+//write_device() sends without waiting and the answer arrives through the interrupt.
+use spin::Once;
+use x86_64::instructions::port::Port;
+
+pub const DATA_PORT: u16 = 0x60;
+const STATUS_PORT: u16 = 0x64;
+const COMMAND_PORT: u16 = 0x64;
+
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+
+const READ_CONFIG: u8 = 0x20;
+const WRITE_CONFIG: u8 = 0x60;
+const DISABLE_SECOND_PORT: u8 = 0xA7;
+const ENABLE_SECOND_PORT: u8 = 0xA8;
+const TEST_SECOND_PORT: u8 = 0xA9;
+const TEST_CONTROLLER: u8 = 0xAA;
+const TEST_FIRST_PORT: u8 = 0xAB;
+const DISABLE_FIRST_PORT: u8 = 0xAD;
+const ENABLE_FIRST_PORT: u8 = 0xAE;
+const WRITE_SECOND_PORT: u8 = 0xD4;
+
+const CONFIG_FIRST_IRQ: u8 = 1 << 0;
+const CONFIG_SECOND_IRQ: u8 = 1 << 1;
+const CONFIG_SECOND_CLOCK_DISABLED: u8 = 1 << 5;
+const CONFIG_TRANSLATION: u8 = 1 << 6;
+
+const CONTROLLER_TEST_PASSED: u8 = 0x55;
+const PORT_TEST_PASSED: u8 = 0x00;
+
+pub const ACK: u8 = 0xFA;
+pub const RESEND: u8 = 0xFE;
+const DEVICE_SELF_TEST_PASSED: u8 = 0xAA;
+const DEVICE_RESET: u8 = 0xFF;
+const KEYBOARD_SCANCODE_SET: u8 = 0xF0;
+const DEVICE_ENABLE_SCANNING: u8 = 0xF4;
+pub const KEYBOARD_SET_LEDS: u8 = 0xED;
+
+/// How many times the status register is polled before giving up, roughly a millisecond
+/// per thousand polls.
+pub const TIMEOUT: usize = 100_000;
+/// Devices may take up to a second to finish their self test after a reset.
+const RESET_TIMEOUT: usize = 1_000_000;
+const RESEND_ATTEMPTS: usize = 3;
+
+static CONTROLLER: Once<Ps2Controller> = Once::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ps2Port {
+    First,
+    Second,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ps2Error {
+    Timeout,
+    ControllerSelfTestFailed(u8),
+    PortTestFailed(Ps2Port, u8),
+    UnexpectedResponse(u8),
+}
+
+/// What `init` found.
+#[derive(Debug, Clone, Copy)]
+pub struct Ps2Controller {
+    pub dual_channel: bool,
+    /// Whether the port passed its test and the device on it answered a reset.
+    pub first_port: bool,
+    pub second_port: bool,
+}
+
+pub fn controller() -> Option<&'static Ps2Controller> {
+    CONTROLLER.get()
+}
+
+/// Resets and tests the controller, then the devices on both ports. The keyboard is left
+/// sending scancode set 2, which the controller translates to set 1.
+/// Has to run before interrupts are enabled.
+pub fn init() -> Result<&'static Ps2Controller, Ps2Error> {
+    if let Some(controller) = CONTROLLER.get() {
+        return Ok(controller);
+    }
+
+    send_command(DISABLE_FIRST_PORT)?;
+    send_command(DISABLE_SECOND_PORT)?;
+    flush_output();
+
+    let mut config = read_config()?;
+    config &= !(CONFIG_FIRST_IRQ | CONFIG_SECOND_IRQ | CONFIG_TRANSLATION);
+    write_config(config)?;
+
+    send_command(TEST_CONTROLLER)?;
+    match read_data(TIMEOUT)? {
+        CONTROLLER_TEST_PASSED => {}
+        response => return Err(Ps2Error::ControllerSelfTestFailed(response)),
+    }
+    // the self test may reset the controller
+    write_config(config)?;
+
+    send_command(ENABLE_SECOND_PORT)?;
+    let dual_channel = read_config()? & CONFIG_SECOND_CLOCK_DISABLED == 0;
+    send_command(DISABLE_SECOND_PORT)?;
+
+    let mut first_port = test_port(Ps2Port::First).is_ok();
+    let mut second_port = dual_channel && test_port(Ps2Port::Second).is_ok();
+    if first_port {
+        send_command(ENABLE_FIRST_PORT)?;
+        first_port = reset_device(Ps2Port::First).is_ok() && init_keyboard().is_ok();
+    }
+    if second_port {
+        send_command(ENABLE_SECOND_PORT)?;
+        second_port = reset_device(Ps2Port::Second).is_ok();
+    }
+
+    if first_port {
+        config |= CONFIG_FIRST_IRQ | CONFIG_TRANSLATION;
+    }
+    if second_port {
+        config |= CONFIG_SECOND_IRQ;
+        config &= !CONFIG_SECOND_CLOCK_DISABLED;
+    }
+    write_config(config)?;
+
+    Ok(CONTROLLER.call_once(|| Ps2Controller {
+        dual_channel,
+        first_port,
+        second_port,
+    }))
+}
+
+fn test_port(port: Ps2Port) -> Result<(), Ps2Error> {
+    send_command(match port {
+        Ps2Port::First => TEST_FIRST_PORT,
+        Ps2Port::Second => TEST_SECOND_PORT,
+    })?;
+    match read_data(TIMEOUT)? {
+        PORT_TEST_PASSED => Ok(()),
+        response => Err(Ps2Error::PortTestFailed(port, response)),
+    }
+}
+
+fn reset_device(port: Ps2Port) -> Result<(), Ps2Error> {
+    device_command(port, DEVICE_RESET)?;
+    match read_data(RESET_TIMEOUT)? {
+        DEVICE_SELF_TEST_PASSED => {}
+        response => return Err(Ps2Error::UnexpectedResponse(response)),
+    }
+    // mice follow up with their device id, which may take a moment to arrive
+    if port == Ps2Port::Second {
+        read_data(TIMEOUT)?;
+    }
+    Ok(())
+}
+
+fn init_keyboard() -> Result<(), Ps2Error> {
+    device_command(Ps2Port::First, KEYBOARD_SCANCODE_SET)?;
+    device_command(Ps2Port::First, 2)?;
+    device_command(Ps2Port::First, DEVICE_ENABLE_SCANNING)
+}
+
+/// Sends `byte` to the device on `port` and waits for it to be acknowledged, resending it
+/// if the device asks for that.
+pub fn device_command(port: Ps2Port, byte: u8) -> Result<(), Ps2Error> {
+    for _ in 0..RESEND_ATTEMPTS {
+        write_device(port, byte)?;
+        match read_data(TIMEOUT)? {
+            ACK => return Ok(()),
+            RESEND => continue,
+            response => return Err(Ps2Error::UnexpectedResponse(response)),
+        }
+    }
+    Err(Ps2Error::UnexpectedResponse(RESEND))
+}
+
+/// Sends `byte` to the device on `port` without waiting for an answer. The answer arrives
+/// through the port's interrupt.
+pub fn write_device(port: Ps2Port, byte: u8) -> Result<(), Ps2Error> {
+    if port == Ps2Port::Second {
+        send_command(WRITE_SECOND_PORT)?;
+    }
+    wait_for_input_empty()?;
+    unsafe { Port::new(DATA_PORT).write(byte) };
+    Ok(())
+}
+
+/// Waits for the next byte from the controller or a device.
+pub fn read_data(timeout: usize) -> Result<u8, Ps2Error> {
+    let mut status = Port::<u8>::new(STATUS_PORT);
+    for _ in 0..timeout {
+        if unsafe { status.read() } & STATUS_OUTPUT_FULL != 0 {
+            return Ok(unsafe { Port::new(DATA_PORT).read() });
+        }
+        core::hint::spin_loop();
+    }
+    Err(Ps2Error::Timeout)
+}
+
+fn send_command(command: u8) -> Result<(), Ps2Error> {
+    wait_for_input_empty()?;
+    unsafe { Port::new(COMMAND_PORT).write(command) };
+    Ok(())
+}
+
+fn read_config() -> Result<u8, Ps2Error> {
+    send_command(READ_CONFIG)?;
+    read_data(TIMEOUT)
+}
+
+fn write_config(config: u8) -> Result<(), Ps2Error> {
+    send_command(WRITE_CONFIG)?;
+    wait_for_input_empty()?;
+    unsafe { Port::new(DATA_PORT).write(config) };
+    Ok(())
+}
+
+fn wait_for_input_empty() -> Result<(), Ps2Error> {
+    let mut status = Port::<u8>::new(STATUS_PORT);
+    for _ in 0..TIMEOUT {
+        if unsafe { status.read() } & STATUS_INPUT_FULL == 0 {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    Err(Ps2Error::Timeout)
+}
+
+fn flush_output() {
+    let mut status = Port::<u8>::new(STATUS_PORT);
+    let mut data = Port::<u8>::new(DATA_PORT);
+    while unsafe { status.read() } & STATUS_OUTPUT_FULL != 0 {
+        unsafe { data.read() };
+    }
+}
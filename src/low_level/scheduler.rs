@@ -0,0 +1,344 @@
+//Preemptive kernel threads. Every thread gets a heap allocated stack and runs until its time
+//slice is used up, it yields, sleeps or exits. The highest priority thread that is ready runs
+//next, threads of the same priority take turns. The thread that called init() becomes the
+//"main" thread, running on the bootloader's stack.
+//Kernel locks keep interrupts off while held, so their holders are never preempted and a higher
+//priority thread can't end up spinning on a lock held by a thread it keeps from running.
+//The scheduler runs inside the timer and yield interrupts, so it never allocates or frees:
+//thread slots are fixed, and the stacks of exited threads are freed by spawn() and the idle thread.
+use alloc::{boxed::Box, vec};
+use core::{arch::asm, time::Duration};
+
+use crate::low_level::{sync::IrqSafeMutex, timer};
+
+pub mod context;
+
+pub const MAX_THREADS: usize = 64;
+pub const STACK_SIZE: usize = 64 * 1024;
+/// The interrupt `yield_now` raises to enter the scheduler.
+pub const YIELD_VECTOR: u8 = 0x81;
+const TIME_SLICE: Duration = Duration::from_millis(10);
+
+static SCHEDULER: IrqSafeMutex<Option<Scheduler>> = IrqSafeMutex::new(None);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Only runs when nothing else can.
+    Idle,
+    Low,
+    Normal,
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThreadId(u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnError {
+    TooManyThreads,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ThreadState {
+    Ready,
+    Running,
+    Sleeping { until: u64 },
+    Exited,
+}
+
+struct Thread {
+    id: ThreadId,
+    priority: Priority,
+    state: ThreadState,
+    stack_pointer: u64,
+    /// Only held so the stack lives as long as the thread. `None` for the main thread,
+    /// which runs on the stack the bootloader set up.
+    _stack: Option<Box<[u64]>>,
+    /// Taken and called by `thread_start` when the thread first runs.
+    entry: Option<Box<dyn FnOnce() + Send>>,
+}
+
+struct Scheduler {
+    threads: [Option<Thread>; MAX_THREADS],
+    current: usize,
+    next_id: u64,
+    slice_end: u64,
+}
+
+impl Scheduler {
+    fn current(&mut self) -> &mut Thread {
+        self.threads[self.current]
+            .as_mut()
+            .expect("the running thread has no slot")
+    }
+
+    /// Saves the running thread at `stack_pointer` and returns the stack pointer of the
+    /// thread that should run next, which may be the same one.
+    fn switch(&mut self, stack_pointer: u64) -> u64 {
+        let now = timer::ticks();
+        let current = self.current();
+        current.stack_pointer = stack_pointer;
+        if current.state == ThreadState::Running {
+            current.state = ThreadState::Ready;
+        }
+        for thread in self.threads.iter_mut().flatten() {
+            if let ThreadState::Sleeping { until } = thread.state {
+                if until <= now {
+                    thread.state = ThreadState::Ready;
+                }
+            }
+        }
+
+        if let Some(next) = self.pick_next() {
+            self.current = next;
+            self.slice_end = now + timer::duration_to_ticks(TIME_SLICE);
+        }
+        let next = self.current();
+        if next.state == ThreadState::Ready {
+            next.state = ThreadState::Running;
+        }
+        next.stack_pointer
+    }
+
+    /// The first ready thread of the highest priority, starting the search after the
+    /// running thread so equal priorities take turns.
+    fn pick_next(&self) -> Option<usize> {
+        (1..=MAX_THREADS)
+            .map(|offset| (self.current + offset) % MAX_THREADS)
+            .filter_map(|index| Some((index, self.threads[index].as_ref()?)))
+            .filter(|(_, thread)| thread.state == ThreadState::Ready)
+            .fold(
+                None,
+                |best: Option<(usize, &Thread)>, (index, thread)| match best {
+                    Some((_, best_thread)) if best_thread.priority >= thread.priority => best,
+                    _ => Some((index, thread)),
+                },
+            )
+            .map(|(index, _)| index)
+    }
+
+    fn insert(&mut self, mut thread: Thread) -> Result<ThreadId, Thread> {
+        let Some(slot) = self.threads.iter_mut().find(|slot| slot.is_none()) else {
+            return Err(thread);
+        };
+        thread.id = ThreadId(self.next_id);
+        self.next_id += 1;
+        let id = thread.id;
+        *slot = Some(thread);
+        Ok(id)
+    }
+
+    /// Removes one exited thread, so it can be dropped outside the scheduler.
+    fn take_exited(&mut self) -> Option<Thread> {
+        let current = self.current;
+        self.threads
+            .iter_mut()
+            .enumerate()
+            .filter(|(index, _)| *index != current)
+            .map(|(_, slot)| slot)
+            .find(|slot| matches!(slot, Some(thread) if thread.state == ThreadState::Exited))?
+            .take()
+    }
+}
+
+/// Turns the calling code into the main thread and starts the idle thread. Needs the heap.
+pub fn init() {
+    let mut threads = [const { None }; MAX_THREADS];
+    threads[0] = Some(Thread {
+        id: ThreadId(0),
+        priority: Priority::Normal,
+        state: ThreadState::Running,
+        stack_pointer: 0,
+        _stack: None,
+        entry: None,
+    });
+    let scheduler = Scheduler {
+        threads,
+        current: 0,
+        next_id: 1,
+        slice_end: 0,
+    };
+    *SCHEDULER.lock() = Some(scheduler);
+    spawn_with_priority(Priority::Idle, idle_thread).expect("starting the idle thread failed");
+}
+
+/// Starts a thread running `entry` at normal priority.
+pub fn spawn(entry: impl FnOnce() + Send + 'static) -> Result<ThreadId, SpawnError> {
+    spawn_with_priority(Priority::Normal, entry)
+}
+
+pub fn spawn_with_priority(
+    priority: Priority,
+    entry: impl FnOnce() + Send + 'static,
+) -> Result<ThreadId, SpawnError> {
+    reap_exited_threads();
+    let mut stack = vec![0u64; STACK_SIZE / 8].into_boxed_slice();
+    let thread = Thread {
+        id: ThreadId(0),
+        priority,
+        state: ThreadState::Ready,
+        stack_pointer: context::initial_stack_pointer(&mut stack, thread_start),
+        _stack: Some(stack),
+        entry: Some(Box::new(entry)),
+    };
+    let inserted = SCHEDULER
+        .lock()
+        .as_mut()
+        .expect("scheduler is not initialized")
+        .insert(thread);
+    // a thread that didn't fit is dropped here, with interrupts enabled
+    inserted.map_err(|_| SpawnError::TooManyThreads)
+}
+
+/// The id of the calling thread.
+pub fn current() -> Option<ThreadId> {
+    with_scheduler(|scheduler| scheduler.current().id)
+}
+
+/// Gives the rest of the time slice to the next ready thread.
+pub fn yield_now() {
+    unsafe { asm!("int {vector}", vector = const YIELD_VECTOR) };
+}
+
+/// Lets other threads run for at least `duration`.
+pub fn sleep(duration: Duration) {
+    let until = timer::ticks() + timer::duration_to_ticks(duration);
+    let sleeping = with_scheduler(|scheduler| {
+        scheduler.current().state = ThreadState::Sleeping { until };
+    });
+    match sleeping {
+        Some(()) => yield_now(),
+        None => timer::sleep_ms(duration.as_millis() as u64),
+    }
+}
+
+/// Ends the calling thread. Its stack is freed later by another thread.
+pub fn exit() -> ! {
+    with_scheduler(|scheduler| scheduler.current().state = ThreadState::Exited);
+    loop {
+        yield_now();
+        x86_64::instructions::hlt();
+    }
+}
+
+fn with_scheduler<T>(f: impl FnOnce(&mut Scheduler) -> T) -> Option<T> {
+    SCHEDULER.lock().as_mut().map(f)
+}
+
+fn reap_exited_threads() {
+    // dropping happens outside `with_scheduler`, so freeing a stack doesn't take the heap
+    // lock while the scheduler is locked
+    while let Some(thread) = with_scheduler(Scheduler::take_exited).flatten() {
+        drop(thread);
+    }
+}
+
+/// Called from the timer interrupt. Switches threads when the time slice is over, or when
+/// the idle thread runs, so woken up sleepers don't wait for its slice to end.
+pub(crate) fn on_timer_tick(stack_pointer: u64) -> u64 {
+    let Some(mut scheduler) = SCHEDULER.try_lock() else {
+        return stack_pointer;
+    };
+    let Some(scheduler) = scheduler.as_mut() else {
+        return stack_pointer;
+    };
+    if timer::ticks() >= scheduler.slice_end || scheduler.current().priority == Priority::Idle {
+        scheduler.switch(stack_pointer)
+    } else {
+        stack_pointer
+    }
+}
+
+/// Called from the yield interrupt.
+pub(crate) fn on_yield(stack_pointer: u64) -> u64 {
+    match SCHEDULER.try_lock().as_deref_mut() {
+        Some(Some(scheduler)) => scheduler.switch(stack_pointer),
+        _ => stack_pointer,
+    }
+}
+
+/// Where every spawned thread starts.
+extern "C" fn thread_start() -> ! {
+    let entry = with_scheduler(|scheduler| scheduler.current().entry.take()).flatten();
+    if let Some(entry) = entry {
+        entry();
+    }
+    exit();
+}
+
+fn idle_thread() {
+    loop {
+        reap_exited_threads();
+        x86_64::instructions::hlt();
+    }
+}
+
+#[test_case]
+fn spawned_threads_run_and_exit() {
+    use alloc::sync::Arc;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    let counter = Arc::new(AtomicUsize::new(0));
+    for _ in 0..4 {
+        let counter = counter.clone();
+        spawn(move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+        })
+        .unwrap();
+    }
+    while counter.load(Ordering::Relaxed) < 4 {
+        yield_now();
+    }
+    // every thread has exited and dropped its clone
+    while Arc::strong_count(&counter) > 1 {
+        yield_now();
+    }
+}
+
+#[test_case]
+fn sleep_lets_other_threads_run() {
+    use alloc::sync::Arc;
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    let ran = Arc::new(AtomicBool::new(false));
+    let thread_ran = ran.clone();
+    spawn_with_priority(Priority::Low, move || {
+        thread_ran.store(true, Ordering::Relaxed)
+    })
+    .unwrap();
+    let start = timer::uptime();
+    sleep(Duration::from_millis(20));
+    assert!(timer::uptime() - start >= Duration::from_millis(20));
+    assert!(ran.load(Ordering::Relaxed));
+}
+
+#[test_case]
+fn high_priority_threads_allocate_alongside_normal_ones() {
+    use alloc::sync::Arc;
+    use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let finished = Arc::new(AtomicUsize::new(0));
+    let (normal_stop, normal_finished) = (stop.clone(), finished.clone());
+    spawn(move || {
+        while !normal_stop.load(Ordering::Relaxed) {
+            core::hint::black_box(vec![0u8; 256]);
+        }
+        normal_finished.fetch_add(1, Ordering::Relaxed);
+    })
+    .unwrap();
+    let high_finished = finished.clone();
+    // wakes up again and again while the normal thread is in the middle of allocating
+    spawn_with_priority(Priority::High, move || {
+        for _ in 0..20 {
+            sleep(Duration::from_millis(2));
+            core::hint::black_box(vec![0u8; 256]);
+        }
+        stop.store(true, Ordering::Relaxed);
+        high_finished.fetch_add(1, Ordering::Relaxed);
+    })
+    .unwrap();
+    while finished.load(Ordering::Relaxed) < 2 {
+        yield_now();
+    }
+}
@@ -0,0 +1,115 @@
+//Saving and restoring threads. The timer and yield interrupts enter through the stubs below,
+//which push every general purpose register on top of the interrupt frame and hand the
+//resulting stack pointer to Rust. Whatever stack pointer Rust returns is where they pop the
+//registers from and `iretq` to, so returning another thread's saved stack pointer switches to it.
+use core::{arch::global_asm, mem::size_of};
+use x86_64::{
+    instructions::segmentation::{Segment, CS, SS},
+    registers::rflags::RFlags,
+};
+
+use crate::low_level::interrupts::{timer_interrupt_handler, yield_interrupt_handler};
+
+/// A stopped thread's registers, in the order the entry stubs leave them on its stack.
+#[derive(Debug, Default)]
+#[repr(C)]
+pub struct SavedRegisters {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    // pushed by the CPU when the interrupt happened
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+macro_rules! switching_interrupt_entry {
+    ($entry:literal, $handler:path) => {
+        global_asm!(
+            concat!(".global ", $entry),
+            concat!($entry, ":"),
+            "push rax",
+            "push rbx",
+            "push rcx",
+            "push rdx",
+            "push rsi",
+            "push rdi",
+            "push rbp",
+            "push r8",
+            "push r9",
+            "push r10",
+            "push r11",
+            "push r12",
+            "push r13",
+            "push r14",
+            "push r15",
+            // the CPU left rsp 8 bytes off 16 byte alignment, the 15 pushes fixed that
+            "mov rdi, rsp",
+            "cld",
+            "call {handler}",
+            "mov rsp, rax",
+            "pop r15",
+            "pop r14",
+            "pop r13",
+            "pop r12",
+            "pop r11",
+            "pop r10",
+            "pop r9",
+            "pop r8",
+            "pop rbp",
+            "pop rdi",
+            "pop rsi",
+            "pop rdx",
+            "pop rcx",
+            "pop rbx",
+            "pop rax",
+            "iretq",
+            handler = sym $handler,
+        );
+    };
+}
+
+switching_interrupt_entry!("timer_interrupt_entry", timer_interrupt_handler);
+switching_interrupt_entry!("yield_interrupt_entry", yield_interrupt_handler);
+
+extern "C" {
+    pub fn timer_interrupt_entry();
+    pub fn yield_interrupt_entry();
+}
+
+/// Prepares `stack` so that switching to the returned stack pointer starts running `entry`
+/// at the top of the stack, with interrupts enabled.
+pub fn initial_stack_pointer(stack: &mut [u64], entry: extern "C" fn() -> !) -> u64 {
+    let stack_end = stack.as_mut_ptr_range().end as u64 & !0xF;
+    // `entry` is jumped to as if it was called, so it expects a return address on the stack
+    let entry_stack_pointer = stack_end - size_of::<u64>() as u64;
+    let registers = SavedRegisters {
+        rip: entry as usize as u64,
+        cs: CS::get_reg().0.into(),
+        // bit 1 is reserved and always set
+        rflags: RFlags::INTERRUPT_FLAG.bits() | 1 << 1,
+        rsp: entry_stack_pointer,
+        ss: SS::get_reg().0.into(),
+        ..SavedRegisters::default()
+    };
+    let stack_pointer = entry_stack_pointer - size_of::<SavedRegisters>() as u64;
+    unsafe {
+        (entry_stack_pointer as *mut u64).write(0);
+        (stack_pointer as *mut SavedRegisters).write(registers);
+    }
+    stack_pointer
+}
@@ -0,0 +1,95 @@
+use core::{
+    fmt::{self, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+use lazy_static::lazy_static;
+use x86_64::instructions::port::{Port, PortReadOnly};
+
+use crate::low_level::sync::IrqSafeMutex;
+
+const COM1: u16 = 0x3F8;
+const LINE_STATUS_TRANSMIT_EMPTY: u8 = 1 << 5;
+
+lazy_static! {
+    pub static ref SERIAL1: IrqSafeMutex<SerialWriter> = {
+        let mut serial_writer = unsafe { SerialWriter::new(COM1) };
+        serial_writer.init();
+        IrqSafeMutex::new(serial_writer)
+    };
+}
+
+/// Whether `log!`, `warn!` and `error!` are also written to the serial port.
+static MIRROR_LOGS: AtomicBool = AtomicBool::new(true);
+
+/// Driver for a 16550 UART, configured for 38400 baud 8N1 with polled output.
+pub struct SerialWriter {
+    data: Port<u8>,
+    interrupt_enable: Port<u8>,
+    fifo_control: Port<u8>,
+    line_control: Port<u8>,
+    modem_control: Port<u8>,
+    line_status: PortReadOnly<u8>,
+}
+
+impl SerialWriter {
+    /// # Safety
+    /// `base` must be the I/O port base of a 16550 compatible UART.
+    pub const unsafe fn new(base: u16) -> Self {
+        SerialWriter {
+            data: Port::new(base),
+            interrupt_enable: Port::new(base + 1),
+            fifo_control: Port::new(base + 2),
+            line_control: Port::new(base + 3),
+            modem_control: Port::new(base + 4),
+            line_status: PortReadOnly::new(base + 5),
+        }
+    }
+
+    pub fn init(&mut self) {
+        unsafe {
+            self.interrupt_enable.write(0x00); // no interrupts, output is polled
+            self.line_control.write(0x80); // DLAB on, the next two ports set the divisor
+            self.data.write(0x03); // divisor 3 => 38400 baud
+            self.interrupt_enable.write(0x00);
+            self.line_control.write(0x03); // DLAB off, 8 data bits, no parity, 1 stop bit
+            self.fifo_control.write(0xC7); // enable and clear FIFOs, 14 byte threshold
+            self.modem_control.write(0x0B); // DTR, RTS and OUT2 set
+        }
+    }
+
+    pub fn write_byte(&mut self, byte: u8) {
+        unsafe {
+            while self.line_status.read() & LINE_STATUS_TRANSMIT_EMPTY == 0 {
+                core::hint::spin_loop();
+            }
+            self.data.write(byte);
+        }
+    }
+}
+
+impl fmt::Write for SerialWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.write_byte(byte);
+        }
+        Ok(())
+    }
+}
+
+pub fn print(args: fmt::Arguments) {
+    SERIAL1
+        .lock()
+        .write_fmt(args)
+        .expect("printing to serial failed");
+}
+
+pub fn set_log_mirroring(enabled: bool) {
+    MIRROR_LOGS.store(enabled, Ordering::Relaxed);
+}
+
+/// Used by `log!`, `warn!` and `error!` to copy their line to the serial port.
+pub fn mirror_log(line: fmt::Arguments) {
+    if MIRROR_LOGS.load(Ordering::Relaxed) {
+        print(line);
+    }
+}
@@ -0,0 +1,130 @@
+//Locks for kernel data that interrupt handlers may touch too. All of them keep interrupts
+//disabled while held, so an interrupt can never try to take a lock the code it interrupted
+//holds. On one CPU, finding such a lock taken therefore always means recursive locking.
+//With the lock-debug feature every lock remembers where it was taken, and trying to take it
+//again from the same CPU panics with both locations instead of hanging.
+use core::panic::Location;
+use x86_64::instructions::interrupts;
+
+pub use irq_safe_mutex::{IrqSafeMutex, IrqSafeMutexGuard};
+pub use rw_lock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use ticket_lock::{TicketLock, TicketLockGuard};
+
+mod irq_safe_mutex;
+mod rw_lock;
+mod ticket_lock;
+
+/// Disables interrupts until dropped, then restores the state from before.
+struct InterruptGuard {
+    were_enabled: bool,
+}
+
+impl InterruptGuard {
+    fn new() -> Self {
+        let were_enabled = interrupts::are_enabled();
+        interrupts::disable();
+        InterruptGuard { were_enabled }
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        if self.were_enabled {
+            interrupts::enable();
+        }
+    }
+}
+
+/// Where a lock was taken, and on which CPU. Only tracked with the lock-debug feature.
+#[cfg(feature = "lock-debug")]
+struct Owner {
+    location: core::sync::atomic::AtomicPtr<Location<'static>>,
+    cpu: core::sync::atomic::AtomicU32,
+}
+
+#[cfg(feature = "lock-debug")]
+impl Owner {
+    const fn new() -> Self {
+        Owner {
+            location: core::sync::atomic::AtomicPtr::new(core::ptr::null_mut()),
+            cpu: core::sync::atomic::AtomicU32::new(0),
+        }
+    }
+
+    fn set(&self, location: &'static Location<'static>) {
+        use core::sync::atomic::Ordering;
+
+        self.cpu
+            .store(crate::low_level::apic::current_cpu_id(), Ordering::Relaxed);
+        self.location
+            .store(location as *const _ as *mut _, Ordering::Relaxed);
+    }
+
+    fn clear(&self) {
+        self.location
+            .store(core::ptr::null_mut(), core::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Called when the lock turned out to be taken. Panics if this CPU is the one holding it.
+    fn check_recursion(&self, lock: &str, location: &'static Location<'static>) {
+        use core::sync::atomic::Ordering;
+
+        let owner = self.location.load(Ordering::Relaxed);
+        let cpu = self.cpu.load(Ordering::Relaxed);
+        if !owner.is_null() && cpu == crate::low_level::apic::current_cpu_id() {
+            let owner = unsafe { &*owner };
+            panic!(
+                "recursive {} locking at {}, already held since {} on CPU {}",
+                lock, location, owner, cpu
+            );
+        }
+    }
+}
+
+#[cfg(not(feature = "lock-debug"))]
+struct Owner;
+
+#[cfg(not(feature = "lock-debug"))]
+impl Owner {
+    const fn new() -> Self {
+        Owner
+    }
+
+    #[inline(always)]
+    fn set(&self, _location: &'static Location<'static>) {}
+
+    #[inline(always)]
+    fn clear(&self) {}
+
+    #[inline(always)]
+    fn check_recursion(&self, _lock: &str, _location: &'static Location<'static>) {}
+}
+
+#[test_case]
+fn locks_restore_the_interrupt_flag() {
+    let mutex = IrqSafeMutex::new(0);
+    let enabled = interrupts::are_enabled();
+    {
+        let mut value = mutex.lock();
+        *value += 1;
+        assert!(!interrupts::are_enabled());
+        assert!(mutex.try_lock().is_none());
+    }
+    assert_eq!(interrupts::are_enabled(), enabled);
+    assert_eq!(*mutex.lock(), 1);
+}
+
+#[test_case]
+fn rw_lock_shares_reads_and_excludes_writes() {
+    let lock = RwLock::new(1);
+    {
+        let first = lock.read();
+        let second = lock.read();
+        assert_eq!(*first + *second, 2);
+        assert!(lock.try_write().is_none());
+    }
+    *lock.write() = 5;
+    assert_eq!(*lock.try_read().unwrap(), 5);
+    let ticket_lock = TicketLock::new(*lock.read());
+    assert_eq!(*ticket_lock.lock(), 5);
+}
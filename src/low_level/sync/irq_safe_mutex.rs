@@ -0,0 +1,129 @@
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    ops::{Deref, DerefMut},
+    panic::Location,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use super::{InterruptGuard, Owner};
+
+/// A spinlock that disables interrupts while it is held.
+pub struct IrqSafeMutex<T: ?Sized> {
+    locked: AtomicBool,
+    owner: Owner,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for IrqSafeMutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for IrqSafeMutex<T> {}
+
+pub struct IrqSafeMutexGuard<'a, T: ?Sized> {
+    mutex: &'a IrqSafeMutex<T>,
+    _interrupts: InterruptGuard,
+}
+
+impl<T> IrqSafeMutex<T> {
+    pub const fn new(data: T) -> Self {
+        IrqSafeMutex {
+            locked: AtomicBool::new(false),
+            owner: Owner::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> IrqSafeMutex<T> {
+    #[track_caller]
+    pub fn lock(&self) -> IrqSafeMutexGuard<'_, T> {
+        let location = Location::caller();
+        let interrupts = InterruptGuard::new();
+        if !self.acquire() {
+            self.owner.check_recursion("IrqSafeMutex", location);
+            while !self.acquire() {
+                core::hint::spin_loop();
+            }
+        }
+        self.owner.set(location);
+        IrqSafeMutexGuard {
+            mutex: self,
+            _interrupts: interrupts,
+        }
+    }
+
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<IrqSafeMutexGuard<'_, T>> {
+        let interrupts = InterruptGuard::new();
+        if !self.acquire() {
+            return None;
+        }
+        self.owner.set(Location::caller());
+        Some(IrqSafeMutexGuard {
+            mutex: self,
+            _interrupts: interrupts,
+        })
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
+    /// # Safety
+    /// Only for code that can't continue otherwise, like the panic handler: whoever held
+    /// the lock may still be using the data.
+    pub unsafe fn force_unlock(&self) {
+        self.owner.clear();
+        self.locked.store(false, Ordering::Release);
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn acquire(&self) -> bool {
+        self.locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+}
+
+impl<T: Default> Default for IrqSafeMutex<T> {
+    fn default() -> Self {
+        IrqSafeMutex::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for IrqSafeMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => write!(f, "IrqSafeMutex {{ data: {:?} }}", &*guard),
+            None => write!(f, "IrqSafeMutex {{ <locked> }}"),
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for IrqSafeMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for IrqSafeMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for IrqSafeMutexGuard<'_, T> {
+    /// Releases the lock. Interrupts are restored afterwards, when `_interrupts` is dropped.
+    fn drop(&mut self) {
+        self.mutex.owner.clear();
+        self.mutex.locked.store(false, Ordering::Release);
+    }
+}
@@ -0,0 +1,158 @@
+use core::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    panic::Location,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use super::{InterruptGuard, Owner};
+
+const WRITER: usize = 1 << (usize::BITS - 1);
+
+/// A lock that any number of readers or one writer can hold. Interrupts are disabled while
+/// it is held either way. Readers are let in whenever no writer holds the lock, so a writer
+/// may have to wait for a steady stream of readers to dry up.
+pub struct RwLock<T: ?Sized> {
+    /// `WRITER` if a writer holds the lock, otherwise the number of readers.
+    state: AtomicUsize,
+    /// Tracks the writer, readers are not recorded.
+    owner: Owner,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+    _interrupts: InterruptGuard,
+}
+
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+    _interrupts: InterruptGuard,
+}
+
+impl<T> RwLock<T> {
+    pub const fn new(data: T) -> Self {
+        RwLock {
+            state: AtomicUsize::new(0),
+            owner: Owner::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    #[track_caller]
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        let location = Location::caller();
+        let interrupts = InterruptGuard::new();
+        if !self.acquire_read() {
+            self.owner.check_recursion("RwLock read", location);
+            while !self.acquire_read() {
+                core::hint::spin_loop();
+            }
+        }
+        RwLockReadGuard {
+            lock: self,
+            _interrupts: interrupts,
+        }
+    }
+
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        let interrupts = InterruptGuard::new();
+        self.acquire_read().then_some(RwLockReadGuard {
+            lock: self,
+            _interrupts: interrupts,
+        })
+    }
+
+    #[track_caller]
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        let location = Location::caller();
+        let interrupts = InterruptGuard::new();
+        if !self.acquire_write() {
+            self.owner.check_recursion("RwLock write", location);
+            while !self.acquire_write() {
+                core::hint::spin_loop();
+            }
+        }
+        self.owner.set(location);
+        RwLockWriteGuard {
+            lock: self,
+            _interrupts: interrupts,
+        }
+    }
+
+    #[track_caller]
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        let interrupts = InterruptGuard::new();
+        if !self.acquire_write() {
+            return None;
+        }
+        self.owner.set(Location::caller());
+        Some(RwLockWriteGuard {
+            lock: self,
+            _interrupts: interrupts,
+        })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn acquire_read(&self) -> bool {
+        let state = self.state.load(Ordering::Relaxed);
+        state & WRITER == 0
+            && self
+                .state
+                .compare_exchange_weak(state, state + 1, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+    }
+
+    fn acquire_write(&self) -> bool {
+        self.state
+            .compare_exchange_weak(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(1, Ordering::Release);
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.owner.clear();
+        self.lock.state.store(0, Ordering::Release);
+    }
+}
@@ -0,0 +1,104 @@
+use core::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    panic::Location,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use super::{InterruptGuard, Owner};
+
+/// A spinlock that hands itself out in the order it was asked for, so no waiter starves.
+/// Like `IrqSafeMutex`, it disables interrupts while held.
+pub struct TicketLock<T: ?Sized> {
+    next_ticket: AtomicUsize,
+    now_serving: AtomicUsize,
+    owner: Owner,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for TicketLock<T> {}
+unsafe impl<T: ?Sized + Send> Sync for TicketLock<T> {}
+
+pub struct TicketLockGuard<'a, T: ?Sized> {
+    lock: &'a TicketLock<T>,
+    _interrupts: InterruptGuard,
+}
+
+impl<T> TicketLock<T> {
+    pub const fn new(data: T) -> Self {
+        TicketLock {
+            next_ticket: AtomicUsize::new(0),
+            now_serving: AtomicUsize::new(0),
+            owner: Owner::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> TicketLock<T> {
+    #[track_caller]
+    pub fn lock(&self) -> TicketLockGuard<'_, T> {
+        let location = Location::caller();
+        let interrupts = InterruptGuard::new();
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        if self.now_serving.load(Ordering::Acquire) != ticket {
+            self.owner.check_recursion("TicketLock", location);
+            while self.now_serving.load(Ordering::Acquire) != ticket {
+                core::hint::spin_loop();
+            }
+        }
+        self.owner.set(location);
+        TicketLockGuard {
+            lock: self,
+            _interrupts: interrupts,
+        }
+    }
+
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<TicketLockGuard<'_, T>> {
+        let interrupts = InterruptGuard::new();
+        let ticket = self.now_serving.load(Ordering::Relaxed);
+        // only take a ticket if it would be served right away
+        self.next_ticket
+            .compare_exchange(ticket, ticket + 1, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        self.owner.set(Location::caller());
+        Some(TicketLockGuard {
+            lock: self,
+            _interrupts: interrupts,
+        })
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.next_ticket.load(Ordering::Relaxed) != self.now_serving.load(Ordering::Relaxed)
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+}
+
+impl<T: ?Sized> Deref for TicketLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for TicketLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for TicketLockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.owner.clear();
+        self.lock.now_serving.fetch_add(1, Ordering::Release);
+    }
+}
@@ -0,0 +1,125 @@
+use alloc::{collections::BTreeMap, sync::Arc, task::Wake};
+use core::task::{Context, Poll, Waker};
+use crossbeam_queue::ArrayQueue;
+use x86_64::instructions::interrupts;
+
+use super::{Task, TaskId};
+
+const TASK_QUEUE_CAPACITY: usize = 100;
+
+pub struct Executor {
+    tasks: BTreeMap<TaskId, Task>,
+    /// Tasks that were woken and have to be polled again. Wakers push to it, possibly from
+    /// interrupt handlers, which is why it is a lock-free queue.
+    task_queue: Arc<ArrayQueue<TaskId>>,
+    waker_cache: BTreeMap<TaskId, Waker>,
+}
+
+impl Executor {
+    pub fn new() -> Self {
+        Executor {
+            tasks: BTreeMap::new(),
+            task_queue: Arc::new(ArrayQueue::new(TASK_QUEUE_CAPACITY)),
+            waker_cache: BTreeMap::new(),
+        }
+    }
+
+    pub fn spawn(&mut self, task: Task) {
+        let task_id = task.id;
+        if self.tasks.insert(task.id, task).is_some() {
+            panic!("task with same ID already in tasks");
+        }
+        self.task_queue.push(task_id).expect("task queue full");
+    }
+
+    /// Runs the tasks forever, halting whenever none of them is ready.
+    pub fn run(&mut self) -> ! {
+        loop {
+            self.run_ready_tasks();
+            self.sleep_if_idle();
+        }
+    }
+
+    /// Polls every woken task once. Returns whether any tasks are left.
+    pub fn run_ready_tasks(&mut self) -> bool {
+        while let Some(task_id) = self.task_queue.pop() {
+            let Some(task) = self.tasks.get_mut(&task_id) else {
+                continue; // the task already finished
+            };
+            let waker = self
+                .waker_cache
+                .entry(task_id)
+                .or_insert_with(|| TaskWaker::new_waker(task_id, self.task_queue.clone()));
+            let mut context = Context::from_waker(waker);
+            if let Poll::Ready(()) = task.poll(&mut context) {
+                self.tasks.remove(&task_id);
+                self.waker_cache.remove(&task_id);
+            }
+        }
+        !self.tasks.is_empty()
+    }
+
+    fn sleep_if_idle(&self) {
+        // an interrupt between the check and `hlt` could wake a task we would then sleep on
+        interrupts::disable();
+        if self.task_queue.is_empty() {
+            interrupts::enable_and_hlt();
+        } else {
+            interrupts::enable();
+        }
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct TaskWaker {
+    task_id: TaskId,
+    task_queue: Arc<ArrayQueue<TaskId>>,
+}
+
+impl TaskWaker {
+    fn new_waker(task_id: TaskId, task_queue: Arc<ArrayQueue<TaskId>>) -> Waker {
+        Waker::from(Arc::new(TaskWaker {
+            task_id,
+            task_queue,
+        }))
+    }
+
+    fn wake_task(&self) {
+        // a full queue means the task is queued already, most likely more than once
+        let _ = self.task_queue.push(self.task_id);
+    }
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_task();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.wake_task();
+    }
+}
+
+#[test_case]
+fn runs_spawned_tasks_to_completion() {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    static POLLED: AtomicUsize = AtomicUsize::new(0);
+    async fn count() {
+        POLLED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(count()));
+    executor.spawn(Task::new(async {
+        count().await;
+        count().await;
+    }));
+    assert!(!executor.run_ready_tasks());
+    assert_eq!(POLLED.load(Ordering::Relaxed), 3);
+}
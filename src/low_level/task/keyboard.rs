@@ -0,0 +1,124 @@
+//The keyboard interrupt only queues the raw scancode and wakes the ScancodeStream, the
+//decoding and everything the keys trigger runs in the `process_keypresses` task.
+//Consumers that want presses and releases can read a `KeyEventStream` instead.
+use crate::{
+    low_level::keyboard::{KeyEvent, KeyboardDecoder},
+    userspace::user_interface::handle_key_event,
+};
+use conquer_once::spin::OnceCell;
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use crossbeam_queue::ArrayQueue;
+use futures_util::{
+    stream::{Stream, StreamExt},
+    task::AtomicWaker,
+};
+
+const SCANCODE_QUEUE_CAPACITY: usize = 100;
+
+static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+/// Called by the keyboard interrupt handler, so it must not block or allocate.
+/// Scancodes are dropped while nobody listens or the queue is full.
+pub(crate) fn add_scancode(scancode: u8) {
+    if let Ok(queue) = SCANCODE_QUEUE.try_get() {
+        if queue.push(scancode).is_ok() {
+            WAKER.wake();
+        }
+    }
+}
+
+/// The scancodes read by the keyboard interrupt. There can only be one.
+pub struct ScancodeStream {
+    _private: (),
+}
+
+impl ScancodeStream {
+    pub fn new() -> Self {
+        SCANCODE_QUEUE
+            .try_init_once(|| ArrayQueue::new(SCANCODE_QUEUE_CAPACITY))
+            .expect("ScancodeStream::new should only be called once");
+        ScancodeStream { _private: () }
+    }
+}
+
+impl Default for ScancodeStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stream for ScancodeStream {
+    type Item = u8;
+
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<u8>> {
+        let queue = SCANCODE_QUEUE
+            .try_get()
+            .expect("scancode queue not initialized");
+
+        // fast path, no need to register a waker
+        if let Some(scancode) = queue.pop() {
+            return Poll::Ready(Some(scancode));
+        }
+
+        WAKER.register(context.waker());
+        // a scancode may have arrived before the waker was registered
+        match queue.pop() {
+            Some(scancode) => {
+                WAKER.take();
+                Poll::Ready(Some(scancode))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// The decoded key presses and releases. Takes over the `ScancodeStream`, so there can
+/// only be one as well.
+pub struct KeyEventStream {
+    scancodes: ScancodeStream,
+    decoder: KeyboardDecoder,
+}
+
+impl KeyEventStream {
+    pub fn new() -> Self {
+        KeyEventStream {
+            scancodes: ScancodeStream::new(),
+            decoder: KeyboardDecoder::new(),
+        }
+    }
+}
+
+impl Default for KeyEventStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stream for KeyEventStream {
+    type Item = KeyEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<KeyEvent>> {
+        // bytes that don't finish a key event, like prefixes and acknowledgements, are skipped
+        while let Poll::Ready(scancode) = self.scancodes.poll_next_unpin(context) {
+            let Some(scancode) = scancode else {
+                return Poll::Ready(None);
+            };
+            if let Some(event) = self.decoder.add_byte(scancode) {
+                return Poll::Ready(Some(event));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+/// Passes the key events on to the user interface.
+pub async fn process_keypresses() {
+    let mut events = KeyEventStream::new();
+    while let Some(event) = events.next().await {
+        handle_key_event(event);
+    }
+}
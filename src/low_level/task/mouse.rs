@@ -0,0 +1,68 @@
+//The mouse interrupt queues finished events here, `MouseEventStream` hands them to whoever
+//listens. Unlike keys, mouse events need no further decoding.
+use crate::low_level::mouse::MouseEvent;
+use conquer_once::spin::OnceCell;
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use crossbeam_queue::ArrayQueue;
+use futures_util::{stream::Stream, task::AtomicWaker};
+
+const EVENT_QUEUE_CAPACITY: usize = 100;
+
+static EVENT_QUEUE: OnceCell<ArrayQueue<MouseEvent>> = OnceCell::uninit();
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+/// Called from the mouse interrupt handler, so it must not block or allocate.
+/// Events are dropped while nobody listens or the queue is full.
+pub(crate) fn add_event(event: MouseEvent) {
+    if let Ok(queue) = EVENT_QUEUE.try_get() {
+        if queue.push(event).is_ok() {
+            WAKER.wake();
+        }
+    }
+}
+
+/// The events of the PS/2 mouse. There can only be one.
+pub struct MouseEventStream {
+    _private: (),
+}
+
+impl MouseEventStream {
+    pub fn new() -> Self {
+        EVENT_QUEUE
+            .try_init_once(|| ArrayQueue::new(EVENT_QUEUE_CAPACITY))
+            .expect("MouseEventStream::new should only be called once");
+        MouseEventStream { _private: () }
+    }
+}
+
+impl Default for MouseEventStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stream for MouseEventStream {
+    type Item = MouseEvent;
+
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<MouseEvent>> {
+        let queue = EVENT_QUEUE
+            .try_get()
+            .expect("mouse event queue not initialized");
+
+        if let Some(event) = queue.pop() {
+            return Poll::Ready(Some(event));
+        }
+
+        WAKER.register(context.waker());
+        match queue.pop() {
+            Some(event) => {
+                WAKER.take();
+                Poll::Ready(Some(event))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
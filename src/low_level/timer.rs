@@ -0,0 +1,118 @@
+use core::{
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    time::Duration,
+};
+use x86_64::instructions::{interrupts, port::Port};
+
+/// The frequency the PIT's oscillator runs at, in Hz.
+pub const PIT_FREQUENCY: u32 = 1_193_182;
+/// The timer interrupt frequency set up by `init`, in Hz.
+pub const TIMER_FREQUENCY: u32 = 1000;
+
+const CHANNEL_0_DATA_PORT: u16 = 0x40;
+const CHANNEL_2_DATA_PORT: u16 = 0x42;
+const COMMAND_PORT: u16 = 0x43;
+/// Bit 0 gates channel 2, bit 1 connects it to the speaker, bit 5 is its output.
+const CHANNEL_2_CONTROL_PORT: u16 = 0x61;
+/// Channel 0, low byte then high byte, mode 3 (square wave), binary counting.
+const CHANNEL_0_SQUARE_WAVE: u8 = 0b0011_0110;
+/// Channel 2, low byte then high byte, mode 0 (interrupt on terminal count), binary counting.
+const CHANNEL_2_ONE_SHOT: u8 = 0b1011_0000;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+static FREQUENCY: AtomicU32 = AtomicU32::new(TIMER_FREQUENCY);
+
+pub fn init() {
+    set_frequency(TIMER_FREQUENCY);
+}
+
+/// Programs PIT channel 0 to fire the timer interrupt `frequency` times per second.
+/// The PIT can't go below ~19 Hz, lower values are clamped.
+pub fn set_frequency(frequency: u32) {
+    let divisor = (PIT_FREQUENCY / frequency.max(1)).clamp(1, u16::MAX.into()) as u16;
+    let mut command: Port<u8> = Port::new(COMMAND_PORT);
+    let mut data: Port<u8> = Port::new(CHANNEL_0_DATA_PORT);
+    interrupts::without_interrupts(|| unsafe {
+        command.write(CHANNEL_0_SQUARE_WAVE);
+        data.write(divisor as u8);
+        data.write((divisor >> 8) as u8);
+    });
+    FREQUENCY.store(PIT_FREQUENCY / u32::from(divisor), Ordering::Relaxed);
+}
+
+/// Tells the timer that something other than the PIT now drives the timer interrupt,
+/// at `frequency` Hz.
+pub fn set_tick_frequency(frequency: u32) {
+    FREQUENCY.store(frequency, Ordering::Relaxed);
+}
+
+pub fn frequency() -> u32 {
+    FREQUENCY.load(Ordering::Relaxed)
+}
+
+/// Called from the timer interrupt handler.
+pub fn tick() {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Time since the timer was started, with the resolution of one tick.
+pub fn uptime() -> Duration {
+    ticks_to_duration(ticks())
+}
+
+pub fn ticks_to_duration(ticks: u64) -> Duration {
+    Duration::from_micros(ticks * 1_000_000 / u64::from(frequency()))
+}
+
+pub fn duration_to_ticks(duration: Duration) -> u64 {
+    let ticks = duration.as_micros() * u128::from(frequency()) / 1_000_000;
+    // always wait at least one tick, otherwise short sleeps would not sleep at all
+    (ticks as u64).max(1)
+}
+
+/// Halts the CPU until at least `milliseconds` have passed. Interrupts have to be enabled,
+/// otherwise the timer never ticks and this never returns.
+pub fn sleep_ms(milliseconds: u64) {
+    debug_assert!(
+        interrupts::are_enabled(),
+        "sleep_ms with interrupts disabled"
+    );
+    let deadline = ticks() + duration_to_ticks(Duration::from_millis(milliseconds));
+    while ticks() < deadline {
+        x86_64::instructions::hlt();
+    }
+}
+
+/// Spins for `milliseconds` (at most 54) using PIT channel 2, without needing interrupts.
+/// Meant for calibrating other timers against the PIT.
+pub fn pit_busy_wait(milliseconds: u16) {
+    let count = (PIT_FREQUENCY / 1000 * u32::from(milliseconds)).min(u16::MAX.into()) as u16;
+    let mut command: Port<u8> = Port::new(COMMAND_PORT);
+    let mut data: Port<u8> = Port::new(CHANNEL_2_DATA_PORT);
+    let mut control: Port<u8> = Port::new(CHANNEL_2_CONTROL_PORT);
+    unsafe {
+        // gate low and speaker off while the count is loaded
+        let gate = control.read() & !0b11;
+        control.write(gate);
+        command.write(CHANNEL_2_ONE_SHOT);
+        data.write(count as u8);
+        data.write((count >> 8) as u8);
+        // the count starts on the rising edge of the gate
+        control.write(gate | 0b1);
+        while control.read() & (1 << 5) == 0 {
+            core::hint::spin_loop();
+        }
+        control.write(gate);
+    }
+}
+
+#[test_case]
+fn sleep_ms_waits_for_the_deadline() {
+    let start = uptime();
+    sleep_ms(20);
+    assert!(uptime() - start >= Duration::from_millis(20));
+}
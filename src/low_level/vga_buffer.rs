@@ -1,10 +1,14 @@
 use core::fmt;
 use lazy_static::lazy_static;
-use spin::Mutex;
-use x86_64::instructions::interrupts;
 
-use crate::low_level::vga_buffer::writer::Writer;
-mod buffer;
+use crate::low_level::{
+    sync::IrqSafeMutex,
+    vga_buffer::{scrollback::Scrollback, writer::Writer},
+};
+mod ansi;
+pub mod buffer;
+pub mod cursor;
+mod scrollback;
 mod writer;
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -28,21 +32,104 @@ pub enum Color {
     White = 0x0F,
 }
 
-const VGA_BUFFER: usize = 0xb8000;
+impl Color {
+    pub const ALL: [Color; 16] = [
+        Color::Black,
+        Color::Blue,
+        Color::Green,
+        Color::Cyan,
+        Color::Red,
+        Color::Magenta,
+        Color::Brown,
+        Color::LighGrey,
+        Color::DarkGrey,
+        Color::LightBlue,
+        Color::LightGreen,
+        Color::LightCyan,
+        Color::LightRed,
+        Color::LightMagenta,
+        Color::Yellow,
+        Color::White,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Color::Black => "black",
+            Color::Blue => "blue",
+            Color::Green => "green",
+            Color::Cyan => "cyan",
+            Color::Red => "red",
+            Color::Magenta => "magenta",
+            Color::Brown => "brown",
+            Color::LighGrey => "light_grey",
+            Color::DarkGrey => "dark_grey",
+            Color::LightBlue => "light_blue",
+            Color::LightGreen => "light_green",
+            Color::LightCyan => "light_cyan",
+            Color::LightRed => "light_red",
+            Color::LightMagenta => "light_magenta",
+            Color::Yellow => "yellow",
+            Color::White => "white",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Color::ALL.into_iter().find(|color| color.name() == name)
+    }
+}
+
+pub const VGA_BUFFER: usize = 0xb8000;
+/// How many rows that scrolled off the screen are kept.
+pub const SCROLLBACK_ROWS: usize = 4000;
 lazy_static! {
-    pub static ref WRITER: Mutex<Writer> =
-        Mutex::new(Writer::new(0, Color::Yellow, Color::Black, VGA_BUFFER,));
+    pub static ref WRITER: IrqSafeMutex<Writer> =
+        IrqSafeMutex::new(Writer::new(Color::White, Color::Black, VGA_BUFFER));
 }
 pub enum CommandToWriter<'a> {
+    /// Text that may contain ANSI escape sequences, see `writer`.
     Print(fmt::Arguments<'a>),
+    /// Sets the colors, and makes them the ones `ESC[0m` returns to.
     SetColor(Color, Color),
     ClearScreen(Color),
-    Backspace,
-    CursorBack,
-    CursorFront,
+    /// The line being typed and the cursor's position in it, in characters. Replaces what
+    /// the previous `ShowInput` drew, until a newline ends the input.
+    ShowInput(&'a str, usize),
+    /// Writes at the given row and column without moving the cursor or scrolling.
+    WriteAt(usize, usize, fmt::Arguments<'a>),
+    /// Moves the view into the scrollback. Output keeps going to the live screen.
+    ScrollView(ScrollView),
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollView {
+    /// Back by a screen, keeping one row of the previous one.
+    PageUp,
+    PageDown,
+    /// To the oldest row in the scrollback.
+    Oldest,
+    /// Back to the live screen.
+    Live,
 }
 pub fn send_command_to_writer(command: CommandToWriter) {
-    interrupts::without_interrupts(|| {
-        WRITER.lock().handle_command(command);
-    });
+    WRITER.lock().handle_command(command);
+}
+
+/// Starts keeping `SCROLLBACK_ROWS` rows of history. Needs the heap, until then rows that
+/// scroll off the top are lost.
+pub fn init_scrollback() {
+    // allocated before locking, the writer lock keeps interrupts off
+    if let Some(scrollback) = Scrollback::new(SCROLLBACK_ROWS) {
+        WRITER.lock().set_scrollback(scrollback);
+    }
+}
+
+#[test_case]
+fn test_println_simple() {
+    crate::println!("test_println_simple output");
+}
+
+#[test_case]
+fn test_println_many() {
+    for _ in 0..200 {
+        crate::println!("test_println_many output");
+    }
 }
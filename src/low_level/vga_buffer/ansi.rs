@@ -0,0 +1,146 @@
+//Splits printed text into characters and ANSI CSI sequences (ESC '[' parameters final byte),
+//one character at a time, so a sequence may be split over several writes.
+//Other escape sequences are dropped, as are CSI sequences with more parameters than fit.
+use super::Color;
+
+const ESCAPE: char = '\u{1b}';
+const MAX_PARAMETERS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Print(char),
+    /// A finished CSI sequence, with its final character.
+    Csi(Parameters, char),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Parameters {
+    values: [u16; MAX_PARAMETERS],
+    len: usize,
+}
+
+impl Parameters {
+    const fn new() -> Self {
+        Parameters {
+            values: [0; MAX_PARAMETERS],
+            len: 0,
+        }
+    }
+
+    /// The parameter at `index`, or `default` if it is missing or 0.
+    pub fn get(&self, index: usize, default: u16) -> u16 {
+        self.as_slice()
+            .get(index)
+            .copied()
+            .filter(|&value| value != 0)
+            .unwrap_or(default)
+    }
+
+    pub fn as_slice(&self) -> &[u16] {
+        &self.values[..self.len]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    Csi,
+    /// In a sequence that is dropped once its final character comes.
+    Ignore,
+}
+
+pub struct Parser {
+    state: State,
+    parameters: Parameters,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Parser {
+            state: State::Ground,
+            parameters: Parameters::new(),
+        }
+    }
+
+    pub fn advance(&mut self, character: char) -> Option<Action> {
+        match (self.state, character) {
+            (State::Ground, ESCAPE) => self.state = State::Escape,
+            (State::Ground, _) => return Some(Action::Print(character)),
+            (State::Escape, '[') => {
+                self.state = State::Csi;
+                self.parameters = Parameters::new();
+                // the first parameter is there even if it is empty
+                self.parameters.len = 1;
+            }
+            (State::Escape, _) => self.state = State::Ground,
+            (State::Csi, '0'..='9') => {
+                let value = &mut self.parameters.values[self.parameters.len - 1];
+                let digit = character as u16 - '0' as u16;
+                *value = value.saturating_mul(10).saturating_add(digit);
+            }
+            (State::Csi, ';') if self.parameters.len < MAX_PARAMETERS => {
+                self.parameters.len += 1;
+            }
+            (State::Csi | State::Ignore, '@'..='~') => {
+                let state = core::mem::replace(&mut self.state, State::Ground);
+                if state == State::Csi {
+                    return Some(Action::Csi(self.parameters, character));
+                }
+            }
+            // private markers like '?', intermediates, or too many parameters
+            (State::Csi | State::Ignore, ' '..='?') => self.state = State::Ignore,
+            // a control character cancels the sequence
+            (State::Csi | State::Ignore, _) => self.state = State::Ground,
+        }
+        None
+    }
+}
+
+/// The color SGR parameters 30 to 37 (foreground) or 40 to 47 (background) select, by
+/// their last digit, before bold or the 90 and 100 ranges make them bright.
+pub fn color(index: u16) -> Color {
+    match index {
+        0 => Color::Black,
+        1 => Color::Red,
+        2 => Color::Green,
+        3 => Color::Brown,
+        4 => Color::Blue,
+        5 => Color::Magenta,
+        6 => Color::Cyan,
+        _ => Color::LighGrey,
+    }
+}
+
+/// The bright variant of `color`, what VGA shows with the intensity bit set.
+pub fn bright(color: Color) -> Color {
+    Color::ALL[color as usize | 0x08]
+}
+
+#[test_case]
+fn parses_sequences_between_characters() {
+    let mut parser = Parser::new();
+    let actions: alloc::vec::Vec<Action> = "a\u{1b}[1;31mb\u{1b}[K\u{1b}[?25lc"
+        .chars()
+        .filter_map(|character| parser.advance(character))
+        .collect();
+    assert_eq!(actions.len(), 5);
+    assert_eq!(actions[0], Action::Print('a'));
+    let Action::Csi(parameters, 'm') = actions[1] else {
+        panic!("expected an SGR sequence");
+    };
+    assert_eq!(parameters.as_slice(), &[1, 31]);
+    assert_eq!(actions[2], Action::Print('b'));
+    let Action::Csi(parameters, 'K') = actions[3] else {
+        panic!("expected an erase in line sequence");
+    };
+    assert_eq!(parameters.get(0, 0), 0);
+    assert_eq!(actions[4], Action::Print('c'));
+    assert_eq!(bright(color(1)), Color::LightRed);
+}
@@ -8,11 +8,6 @@ pub struct Char {
     pub ascii_character: u8,
     pub color_code: ColorCode,
 }
-impl Char {
-    pub fn invert_colors(&mut self) {
-        self.color_code.invert();
-    }
-}
 #[repr(transparent)]
 pub struct Buffer {
     pub chars: [[Char; BUFFER_WIDTH]; BUFFER_HEIGHT],
@@ -30,8 +25,47 @@ impl ColorCode {
     pub fn get_colors(&self) -> (u8, u8) {
         (self.0 % 16u8, self.0 >> 4u8)
     }
-    pub fn invert(&mut self) {
-        let colors = self.get_colors();
-        *self = Self::generate(colors.1, colors.0)
+}
+
+/// Maps a character to the VGA text mode font (code page 437). Characters the font
+/// does not have are shown as a small square.
+pub fn to_code_page_437(character: char) -> u8 {
+    match character {
+        ' '..='~' | '\n' => character as u8,
+        'Ç' => 0x80,
+        'ü' => 0x81,
+        'é' => 0x82,
+        'â' => 0x83,
+        'ä' => 0x84,
+        'à' => 0x85,
+        'ç' => 0x87,
+        'ê' => 0x88,
+        'ë' => 0x89,
+        'è' => 0x8A,
+        'ï' => 0x8B,
+        'î' => 0x8C,
+        'Ä' => 0x8E,
+        'É' => 0x90,
+        'ô' => 0x93,
+        'ö' => 0x94,
+        'û' => 0x96,
+        'ù' => 0x97,
+        'Ö' => 0x99,
+        'Ü' => 0x9A,
+        'á' => 0xA0,
+        'í' => 0xA1,
+        'ó' => 0xA2,
+        'ú' => 0xA3,
+        'ñ' => 0xA4,
+        'Ñ' => 0xA5,
+        '¿' => 0xA8,
+        '¡' => 0xAD,
+        'ß' => 0xE1,
+        // the font has no accented capitals besides the ones above
+        'Á' => b'A',
+        'Í' => b'I',
+        'Ó' => b'O',
+        'Ú' => b'U',
+        _ => 0xFE,
     }
 }
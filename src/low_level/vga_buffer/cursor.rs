@@ -0,0 +1,49 @@
+//The blinking text mode cursor. The CRT controller draws it, and is programmed by writing a
+//register number to its index port and then the value to its data port.
+use x86_64::instructions::port::Port;
+
+use super::buffer::BUFFER_WIDTH;
+
+const CRTC_INDEX: u16 = 0x3D4;
+const CRTC_DATA: u16 = 0x3D5;
+
+const CURSOR_START: u8 = 0x0A;
+const CURSOR_END: u8 = 0x0B;
+const CURSOR_LOCATION_HIGH: u8 = 0x0E;
+const CURSOR_LOCATION_LOW: u8 = 0x0F;
+const CURSOR_DISABLED: u8 = 1 << 5;
+
+/// The cursor covers these scanlines of the 16 in a character cell, an underline.
+const UNDERLINE_START: u8 = 14;
+const UNDERLINE_END: u8 = 15;
+
+pub fn enable() {
+    unsafe {
+        let start = read_register(CURSOR_START) & 0xC0;
+        write_register(CURSOR_START, start | UNDERLINE_START);
+        let end = read_register(CURSOR_END) & 0xE0;
+        write_register(CURSOR_END, end | UNDERLINE_END);
+    }
+}
+
+pub fn disable() {
+    unsafe { write_register(CURSOR_START, CURSOR_DISABLED) };
+}
+
+pub fn set_position(row: usize, column: usize) {
+    let location = (row * BUFFER_WIDTH + column) as u16;
+    unsafe {
+        write_register(CURSOR_LOCATION_LOW, location as u8);
+        write_register(CURSOR_LOCATION_HIGH, (location >> 8) as u8);
+    }
+}
+
+unsafe fn read_register(register: u8) -> u8 {
+    Port::new(CRTC_INDEX).write(register);
+    Port::new(CRTC_DATA).read()
+}
+
+unsafe fn write_register(register: u8, value: u8) {
+    Port::new(CRTC_INDEX).write(register);
+    Port::new(CRTC_DATA).write(value);
+}
@@ -0,0 +1,79 @@
+//The rows that scrolled off the top of the screen, oldest first. It is a ring, so once it is
+//full every new row replaces the oldest one, and pushing never allocates.
+use alloc::{boxed::Box, vec::Vec};
+
+use super::{
+    buffer::{Char, ColorCode, BUFFER_WIDTH},
+    Color,
+};
+
+pub type Row = [Char; BUFFER_WIDTH];
+
+pub struct Scrollback {
+    rows: Box<[Row]>,
+    /// Index of the oldest row.
+    start: usize,
+    len: usize,
+}
+
+impl Scrollback {
+    /// Room for `capacity` rows, or `None` if the heap doesn't have that much.
+    pub fn new(capacity: usize) -> Option<Self> {
+        // never shown, rows are only read after they were pushed
+        let blank = Char {
+            ascii_character: b' ',
+            color_code: ColorCode::new(Color::Black, Color::Black),
+        };
+        let mut rows = Vec::new();
+        rows.try_reserve_exact(capacity).ok()?;
+        rows.resize(capacity, [blank; BUFFER_WIDTH]);
+        Some(Scrollback {
+            rows: rows.into_boxed_slice(),
+            start: 0,
+            len: 0,
+        })
+    }
+
+    pub fn push(&mut self, row: Row) {
+        let capacity = self.rows.len();
+        if capacity == 0 {
+            return;
+        }
+        if self.len < capacity {
+            self.rows[(self.start + self.len) % capacity] = row;
+            self.len += 1;
+        } else {
+            self.rows[self.start] = row;
+            self.start = (self.start + 1) % capacity;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The row at `index`, 0 being the oldest.
+    pub fn get(&self, index: usize) -> Option<&Row> {
+        (index < self.len).then(|| &self.rows[(self.start + index) % self.rows.len()])
+    }
+}
+
+#[test_case]
+fn keeps_the_newest_rows() {
+    let char = |ascii_character| Char {
+        ascii_character,
+        color_code: ColorCode::new(Color::White, Color::Black),
+    };
+    let mut scrollback = Scrollback::new(3).unwrap();
+    for byte in b'a'..=b'e' {
+        scrollback.push([char(byte); BUFFER_WIDTH]);
+    }
+    assert_eq!(scrollback.len(), 3);
+    assert_eq!(scrollback.get(0).unwrap()[0], char(b'c'));
+    assert_eq!(scrollback.get(2).unwrap()[0], char(b'e'));
+    assert!(scrollback.get(3).is_none());
+}
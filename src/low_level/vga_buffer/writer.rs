@@ -1,71 +1,162 @@
+//Writes text to the whole screen, row by row. Lines wrap at the right edge, and once the
+//bottom row is full everything scrolls up, into the scrollback if there is one.
+//All writing goes to a copy of the screen in memory. After every command, the rows that changed
+//in the part the user is looking at, which may be scrolled back into the history, are copied to
+//the VGA buffer.
+//The hardware cursor follows the text, or the cursor of the input drawn by show_input().
+//Printed text may contain ANSI escape sequences: SGR colors, cursor movement and erasing.
 use super::{
-    buffer::{Buffer, Char, ColorCode, BUFFER_HEIGHT, BUFFER_WIDTH},
-    Color, CommandToWriter,
+    ansi::{self, Action, Parameters, Parser},
+    buffer::{to_code_page_437, Buffer, Char, ColorCode, BUFFER_HEIGHT, BUFFER_WIDTH},
+    cursor,
+    scrollback::Scrollback,
+    Color, CommandToWriter, ScrollView,
 };
 use core::fmt::{self, Write};
-const ACTUAL_BUFFER_WIDTH: usize = 50;
-//Added because input stopped working after user tried to enter the 51 character.
-//Probably qemu issue, maybe there is a way, but this is the temporary fix
+
 pub struct Writer {
-    column_position: usize,
-    color_code: ColorCode,
+    row: usize,
+    /// Where the next character goes. Equal to `BUFFER_WIDTH` when the row is full, the
+    /// wrap only happens once another character comes.
+    column: usize,
+    /// Where the input shown by `show_input` starts, if there is one.
+    input_start: Option<(usize, usize)>,
+    /// How many cells the input drawn by `show_input` took, cleared before the next one.
+    input_length: usize,
+    /// Where `show_input` put the cursor, while that input is on the screen.
+    input_cursor: Option<(usize, usize)>,
+    /// The colors set with `SetColor`, which SGR 0 goes back to.
+    normal_colors: (Color, Color),
+    foreground: Color,
+    background: Color,
+    /// Shows the foreground in its bright variant.
+    bold: bool,
+    parser: Parser,
+    /// The live screen, what the VGA buffer shows when the view isn't scrolled back.
+    screen: Buffer,
+    /// The VGA buffer.
     buffer: &'static mut Buffer,
+    scrollback: Option<Scrollback>,
+    /// How many rows the view is scrolled back into the scrollback, 0 for the live screen.
+    view_offset: usize,
+    /// Rows of `screen` changed since `present` last copied them, one bit per row.
+    dirty_rows: u32,
+    /// Set when every row of the view has to be copied again, after scrolling.
+    redraw: bool,
+    /// Where the hardware cursor is shown, `None` while it is hidden.
+    shown_cursor: Option<(usize, usize)>,
 }
 
 impl Writer {
-    pub fn new(
-        column_position: usize,
-        foreground: Color,
-        background: Color,
-        buffer: usize,
-    ) -> Self {
-        Writer {
-            column_position,
+    pub fn new(foreground: Color, background: Color, buffer: usize) -> Self {
+        let blank = Char {
+            ascii_character: b' ',
             color_code: ColorCode::new(foreground, background),
+        };
+        Writer {
+            row: 0,
+            column: 0,
+            input_start: None,
+            input_length: 0,
+            input_cursor: None,
+            normal_colors: (foreground, background),
+            foreground,
+            background,
+            bold: false,
+            parser: Parser::new(),
+            screen: Buffer {
+                chars: [[blank; BUFFER_WIDTH]; BUFFER_HEIGHT],
+            },
             buffer: unsafe { &mut *(buffer as *mut Buffer) },
+            scrollback: None,
+            view_offset: 0,
+            dirty_rows: 0,
+            redraw: true,
+            shown_cursor: None,
         }
     }
     pub fn handle_command(&mut self, command: CommandToWriter) {
         match command {
-            CommandToWriter::Backspace => self.backspace(),
             CommandToWriter::ClearScreen(color) => self.clear_screen(color),
-            CommandToWriter::CursorBack => self.cursor_back(),
-            CommandToWriter::CursorFront => self.cursor_front(),
+            CommandToWriter::ShowInput(line, cursor) => self.show_input(line, cursor),
             CommandToWriter::Print(args) => self.write_fmt(args).unwrap(),
+            CommandToWriter::WriteAt(row, column, args) => self.write_at(row, column, args),
             CommandToWriter::SetColor(foreground, background) => {
                 self.set_color(foreground, background)
             }
+            CommandToWriter::ScrollView(scroll) => self.scroll_view(scroll),
         }
+        self.present();
     }
-    fn move_cursor(&mut self, column_position: usize) {
-        self.buffer.chars[BUFFER_HEIGHT - 1][self.column_position + 1].invert_colors();
-        if column_position == 0 {
-            self.next_line();
-        } else {
-            self.column_position = column_position;
+
+    /// Keeps the rows that scroll off the top in `scrollback` from now on.
+    pub fn set_scrollback(&mut self, scrollback: Scrollback) {
+        self.scrollback = Some(scrollback);
+    }
+
+    /// The row and column the next character goes to.
+    pub fn position(&self) -> (usize, usize) {
+        (self.row, self.column)
+    }
+
+    /// Writes `args` starting at `row` and `column`, wrapping at the right edge and cut off
+    /// at the bottom. Neither scrolls nor moves the position of the normal output.
+    pub fn write_at(&mut self, row: usize, column: usize, args: fmt::Arguments) {
+        let mut writer = PositionedWriter {
+            row,
+            column,
+            color_code: self.color_code(),
+            buffer: &mut self.screen,
+        };
+        let _ = writer.write_fmt(args);
+        for row in row..=writer.row.min(BUFFER_HEIGHT - 1) {
+            self.mark_dirty(row);
         }
-        self.buffer.chars[BUFFER_HEIGHT - 1][self.column_position + 1].invert_colors();
     }
+
     fn write_byte(&mut self, byte: u8) {
-        if byte == b'\n' || self.column_position >= ACTUAL_BUFFER_WIDTH {
-            self.move_cursor(0);
+        if byte == b'\n' {
+            self.new_line();
             return;
         }
-        self.move_cursor(self.column_position + 1);
-        self.set_char(byte);
-    }
-    fn set_char(&mut self, byte: u8) {
-        self.buffer.chars[BUFFER_HEIGHT - 1][self.column_position] = Char {
+        if self.column >= BUFFER_WIDTH {
+            self.new_line();
+        }
+        self.screen.chars[self.row][self.column] = Char {
             ascii_character: byte,
-            color_code: self.color_code,
+            color_code: self.color_code(),
         };
+        self.column += 1;
+        self.mark_dirty(self.row);
+    }
+
+    fn new_line(&mut self) {
+        if self.row + 1 < BUFFER_HEIGHT {
+            self.row += 1;
+        } else {
+            self.scroll_up();
+        }
+        self.column = 0;
+        self.input_start = None;
+        self.input_cursor = None;
     }
-    fn next_line(&mut self) {
+
+    fn scroll_up(&mut self) {
+        if let Some(scrollback) = &mut self.scrollback {
+            scrollback.push(self.screen.chars[0]);
+            // a view into the history stays on the rows it shows
+            if self.view_offset > 0 {
+                self.view_offset = (self.view_offset + 1).min(scrollback.len());
+            }
+        }
         for row in 1..BUFFER_HEIGHT {
-            self.buffer.chars[row - 1] = self.buffer.chars[row]
+            self.screen.chars[row - 1] = self.screen.chars[row]
         }
         self.clear_row(BUFFER_HEIGHT - 1);
-        self.column_position = 0;
+        self.redraw = true;
+        if let Some((row, _)) = &mut self.input_start {
+            *row = row.saturating_sub(1);
+        }
     }
 
     fn clear_screen(&mut self, color: Color) {
@@ -73,55 +164,400 @@ impl Writer {
             ascii_character: b' ',
             color_code: ColorCode::new(color, color),
         };
-        for row in 0..BUFFER_HEIGHT {
-            for col in 0..BUFFER_WIDTH {
-                self.buffer.chars[row][col] = blank;
-            }
+        for row in self.screen.chars.iter_mut() {
+            row.fill(blank);
         }
+        self.redraw = true;
+        self.row = 0;
+        self.column = 0;
+        self.input_start = None;
+        self.input_cursor = None;
     }
 
     fn clear_row(&mut self, row: usize) {
-        let blank = Char {
+        let blank = self.blank();
+        self.screen.chars[row].fill(blank);
+        self.mark_dirty(row);
+    }
+
+    fn mark_dirty(&mut self, row: usize) {
+        self.dirty_rows |= 1 << row;
+    }
+
+    fn blank(&self) -> Char {
+        Char {
             ascii_character: b' ',
-            color_code: self.color_code,
-        };
-        for col in 0..BUFFER_WIDTH {
-            self.buffer.chars[row][col] = blank;
+            color_code: self.color_code(),
         }
     }
+
     fn write_string(&mut self, s: &str) {
-        for byte in s.bytes() {
-            self.write_byte(byte)
+        for character in s.chars() {
+            match self.parser.advance(character) {
+                Some(Action::Print(character)) => self.write_byte(to_code_page_437(character)),
+                Some(Action::Csi(parameters, action)) => self.handle_csi(parameters, action),
+                None => {}
+            }
+        }
+    }
+
+    fn handle_csi(&mut self, parameters: Parameters, action: char) {
+        let count = usize::from(parameters.get(0, 1));
+        let column = self.column.min(BUFFER_WIDTH - 1);
+        match action {
+            'm' => self.select_graphic_rendition(parameters),
+            'A' => self.move_to(self.row.saturating_sub(count), column),
+            'B' => self.move_to(self.row + count, column),
+            'C' => self.move_to(self.row, column + count),
+            'D' => self.move_to(self.row, column.saturating_sub(count)),
+            'G' => self.move_to(self.row, count - 1),
+            'H' | 'f' => self.move_to(count - 1, usize::from(parameters.get(1, 1)) - 1),
+            'J' => self.erase_in_display(parameters.get(0, 0)),
+            'K' => self.erase_in_line(parameters.get(0, 0)),
+            _ => {}
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, parameters: Parameters) {
+        for &parameter in parameters.as_slice() {
+            match parameter {
+                0 => {
+                    (self.foreground, self.background) = self.normal_colors;
+                    self.bold = false;
+                }
+                1 => self.bold = true,
+                22 => self.bold = false,
+                30..=37 => self.foreground = ansi::color(parameter - 30),
+                39 => self.foreground = self.normal_colors.0,
+                40..=47 => self.background = ansi::color(parameter - 40),
+                49 => self.background = self.normal_colors.1,
+                90..=97 => self.foreground = ansi::bright(ansi::color(parameter - 90)),
+                100..=107 => self.background = ansi::bright(ansi::color(parameter - 100)),
+                _ => {}
+            }
         }
     }
 
+    /// Moves the position of the normal output, kept on the screen. Whatever comes next is
+    /// not part of the input any more.
+    fn move_to(&mut self, row: usize, column: usize) {
+        self.row = row.min(BUFFER_HEIGHT - 1);
+        self.column = column.min(BUFFER_WIDTH - 1);
+        self.input_start = None;
+        self.input_cursor = None;
+    }
+
+    /// 0 erases from the position to the end of the screen, 1 from the start of the screen
+    /// to the position, 2 and 3 all of it. The position stays where it is.
+    fn erase_in_display(&mut self, mode: u16) {
+        let rows = match mode {
+            0 => self.row + 1..BUFFER_HEIGHT,
+            1 => 0..self.row,
+            2 | 3 => 0..BUFFER_HEIGHT,
+            _ => return,
+        };
+        for row in rows {
+            self.clear_row(row);
+        }
+        if mode < 2 {
+            self.erase_in_line(mode);
+        }
+    }
+
+    /// Like `erase_in_display`, within the row of the position.
+    fn erase_in_line(&mut self, mode: u16) {
+        let blank = self.blank();
+        let column = self.column;
+        let row = &mut self.screen.chars[self.row];
+        match mode {
+            0 => row[column..].fill(blank),
+            1 => row[..=column.min(BUFFER_WIDTH - 1)].fill(blank),
+            2 => row.fill(blank),
+            _ => {}
+        }
+        self.mark_dirty(self.row);
+    }
+
     fn set_color(&mut self, foreground: Color, background: Color) {
-        self.color_code = ColorCode::new(foreground, background);
+        self.normal_colors = (foreground, background);
+        (self.foreground, self.background) = self.normal_colors;
+        self.bold = false;
     }
-    fn backspace(&mut self) {
-        if self.column_position == 0 {
-            return;
+
+    fn color_code(&self) -> ColorCode {
+        let foreground = match self.bold {
+            true => ansi::bright(self.foreground),
+            false => self.foreground,
+        };
+        ColorCode::new(foreground, self.background)
+    }
+
+    /// Draws `line` where the input started, replacing the previous one, and puts the
+    /// hardware cursor on its `cursor`th character. Long lines wrap, scrolling the screen
+    /// if they run past the bottom. Normal output continues after the end of the line.
+    fn show_input(&mut self, line: &str, cursor: usize) {
+        if self.input_start.is_none() {
+            if self.column >= BUFFER_WIDTH {
+                self.new_line();
+            }
+            self.input_start = Some((self.row, self.column));
+            self.input_length = 0;
+        }
+        let length = line.chars().count();
+        let (start_row, start_column) = self.input_start.unwrap_or_default();
+        // the row the cursor is on after the last character has to fit as well
+        let rows = (start_column + length) / BUFFER_WIDTH + 1;
+        for _ in 0..(start_row + rows)
+            .saturating_sub(BUFFER_HEIGHT)
+            .min(start_row)
+        {
+            self.scroll_up();
+        }
+        let start = self.input_start.unwrap_or_default();
+
+        // only the previous input, anything written below it with write_at stays
+        let blank = self.blank();
+        for offset in 0..self.input_length {
+            let (row, column) = offset_position(start, offset);
+            if row >= BUFFER_HEIGHT {
+                break;
+            }
+            self.screen.chars[row][column] = blank;
+        }
+        let (last_row, _) = offset_position(start, self.input_length.max(length));
+        for row in start.0..=last_row.min(BUFFER_HEIGHT - 1) {
+            self.mark_dirty(row);
+        }
+        self.input_length = length;
+        for (offset, character) in line.chars().enumerate() {
+            let (row, column) = offset_position(start, offset);
+            if row >= BUFFER_HEIGHT {
+                break;
+            }
+            self.screen.chars[row][column] = Char {
+                ascii_character: to_code_page_437(character),
+                color_code: self.color_code(),
+            };
+        }
+
+        (self.row, self.column) = offset_position(start, length);
+        if self.row >= BUFFER_HEIGHT {
+            (self.row, self.column) = (BUFFER_HEIGHT - 1, BUFFER_WIDTH);
         }
-        self.set_char(b' ');
-        self.move_cursor(self.column_position - 1);
+        let (cursor_row, cursor_column) = offset_position(start, cursor);
+        self.input_cursor = Some((
+            cursor_row.min(BUFFER_HEIGHT - 1),
+            cursor_column.min(BUFFER_WIDTH - 1),
+        ));
     }
-    fn cursor_back(&mut self) {
-        if self.column_position == 0 {
-            return;
+
+    fn scroll_view(&mut self, scroll: ScrollView) {
+        let history = self.scrollback.as_ref().map_or(0, Scrollback::len);
+        let page = BUFFER_HEIGHT - 1;
+        let view_offset = match scroll {
+            ScrollView::PageUp => (self.view_offset + page).min(history),
+            ScrollView::PageDown => self.view_offset.saturating_sub(page),
+            ScrollView::Oldest => history,
+            ScrollView::Live => 0,
+        };
+        if view_offset != self.view_offset {
+            self.view_offset = view_offset;
+            self.redraw = true;
         }
-        self.move_cursor(self.column_position - 1)
     }
-    fn cursor_front(&mut self) {
-        if self.column_position == ACTUAL_BUFFER_WIDTH {
+
+    /// Copies the rows of the view that changed to the VGA buffer and moves the hardware
+    /// cursor, which is hidden while the view is scrolled back.
+    fn present(&mut self) {
+        if self.redraw {
+            let history = self.scrollback.as_ref().map_or(0, Scrollback::len);
+            let top = history - self.view_offset;
+            for (row, visible) in self.buffer.chars.iter_mut().enumerate() {
+                let index = top + row;
+                *visible = match index.checked_sub(history) {
+                    Some(screen_row) => self.screen.chars[screen_row],
+                    None => self
+                        .scrollback
+                        .as_ref()
+                        .and_then(|scrollback| scrollback.get(index))
+                        .copied()
+                        .unwrap_or(self.screen.chars[0]),
+                };
+            }
+        } else {
+            // row `row` of the screen is `view_offset` rows further down in the view
+            for row in 0..BUFFER_HEIGHT.saturating_sub(self.view_offset) {
+                if self.dirty_rows & (1 << row) != 0 {
+                    self.buffer.chars[row + self.view_offset] = self.screen.chars[row];
+                }
+            }
+        }
+        self.redraw = false;
+        self.dirty_rows = 0;
+
+        let cursor = (self.view_offset == 0).then(|| {
+            self.input_cursor
+                .unwrap_or((self.row, self.column.min(BUFFER_WIDTH - 1)))
+        });
+        if cursor == self.shown_cursor {
             return;
         }
-        self.move_cursor(self.column_position + 1)
+        match cursor {
+            None => cursor::disable(),
+            Some((row, column)) => {
+                if self.shown_cursor.is_none() {
+                    cursor::enable();
+                }
+                cursor::set_position(row, column);
+            }
+        }
+        self.shown_cursor = cursor;
     }
 }
 
+/// The cell `offset` characters after `start`, counting across rows.
+fn offset_position((row, column): (usize, usize), offset: usize) -> (usize, usize) {
+    let cell = column + offset;
+    (row + cell / BUFFER_WIDTH, cell % BUFFER_WIDTH)
+}
+
 impl fmt::Write for Writer {
     fn write_str(&mut self, s: &str) -> fmt::Result {
         self.write_string(s);
         Ok(())
     }
 }
+
+/// Used by `Writer::write_at`.
+struct PositionedWriter<'a> {
+    row: usize,
+    column: usize,
+    color_code: ColorCode,
+    buffer: &'a mut Buffer,
+}
+
+impl fmt::Write for PositionedWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for character in s.chars() {
+            if character == '\n' || self.column >= BUFFER_WIDTH {
+                self.row += 1;
+                self.column = 0;
+                if character == '\n' {
+                    continue;
+                }
+            }
+            if self.row >= BUFFER_HEIGHT {
+                break;
+            }
+            self.buffer.chars[self.row][self.column] = Char {
+                ascii_character: to_code_page_437(character),
+                color_code: self.color_code,
+            };
+            self.column += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn test_writer() -> Writer {
+    use alloc::boxed::Box;
+
+    let blank = Char {
+        ascii_character: b' ',
+        color_code: ColorCode::new(Color::White, Color::Black),
+    };
+    let buffer = Box::leak(Box::new(Buffer {
+        chars: [[blank; BUFFER_WIDTH]; BUFFER_HEIGHT],
+    }));
+    Writer::new(Color::White, Color::Black, buffer as *mut Buffer as usize)
+}
+
+#[test_case]
+fn wraps_at_the_full_width_and_scrolls() {
+    let mut writer = test_writer();
+    for _ in 0..BUFFER_HEIGHT - 1 {
+        writer.write_string("\n");
+    }
+    writer.write_string(&"x".repeat(BUFFER_WIDTH));
+    assert_eq!(writer.position(), (BUFFER_HEIGHT - 1, BUFFER_WIDTH));
+    writer.write_string("y");
+    assert_eq!(writer.position(), (BUFFER_HEIGHT - 1, 1));
+    let scrolled = writer.screen.chars[BUFFER_HEIGHT - 2];
+    assert!(scrolled.iter().all(|c| c.ascii_character == b'x'));
+    assert_eq!(
+        writer.screen.chars[BUFFER_HEIGHT - 1][0].ascii_character,
+        b'y'
+    );
+}
+
+#[test_case]
+fn positioned_writes_leave_the_position_alone() {
+    let mut writer = test_writer();
+    writer.write_string("> ");
+    writer.write_at(3, BUFFER_WIDTH - 1, format_args!("ab"));
+    assert_eq!(
+        writer.screen.chars[3][BUFFER_WIDTH - 1].ascii_character,
+        b'a'
+    );
+    assert_eq!(writer.screen.chars[4][0].ascii_character, b'b');
+    assert_eq!(writer.position(), (0, 2));
+
+    let line = "z".repeat(BUFFER_WIDTH);
+    writer.show_input(&line, 0);
+    assert_eq!(writer.screen.chars[1][1].ascii_character, b'z');
+    assert_eq!(writer.position(), (1, 2));
+    writer.show_input("", 0);
+    assert_eq!(writer.screen.chars[1][1].ascii_character, b' ');
+    assert_eq!(
+        writer.screen.chars[3][BUFFER_WIDTH - 1].ascii_character,
+        b'a'
+    );
+    assert_eq!(writer.screen.chars[4][0].ascii_character, b'b');
+}
+
+#[test_case]
+fn the_view_stays_put_while_scrolled_back() {
+    let mut writer = test_writer();
+    writer.set_scrollback(Scrollback::new(100).unwrap());
+    for line in 0..BUFFER_HEIGHT + 10 {
+        writer.handle_command(CommandToWriter::Print(format_args!("{}\n", line)));
+    }
+    writer.handle_command(CommandToWriter::ScrollView(ScrollView::Oldest));
+    assert_eq!(writer.buffer.chars[0][0].ascii_character, b'0');
+    writer.handle_command(CommandToWriter::Print(format_args!("more\n")));
+    assert_eq!(writer.buffer.chars[0][0].ascii_character, b'0');
+    writer.write_at(0, 0, format_args!("hidden"));
+    writer.handle_command(CommandToWriter::Print(format_args!("x")));
+    assert_eq!(writer.buffer.chars[0][0].ascii_character, b'0');
+    writer.handle_command(CommandToWriter::ScrollView(ScrollView::Live));
+    assert_eq!(writer.buffer.chars, writer.screen.chars);
+    writer.handle_command(CommandToWriter::WriteAt(0, 0, format_args!("shown")));
+    assert_eq!(writer.buffer.chars, writer.screen.chars);
+}
+
+#[test_case]
+fn escape_sequences_set_colors_and_erase() {
+    let mut writer = test_writer();
+    writer.write_string("\u{1b}[1;31ma\u{1b}[44mb\u{1b}[0mc");
+    let row = writer.screen.chars[0];
+    assert_eq!(
+        row[0].color_code,
+        ColorCode::new(Color::LightRed, Color::Black)
+    );
+    assert_eq!(
+        row[1].color_code,
+        ColorCode::new(Color::LightRed, Color::Blue)
+    );
+    assert_eq!(
+        row[2].color_code,
+        ColorCode::new(Color::White, Color::Black)
+    );
+
+    writer.write_string("\u{1b}[3;5Hxyz\u{1b}[2D\u{1b}[K");
+    assert_eq!(writer.position(), (2, 5));
+    assert_eq!(writer.screen.chars[2][4].ascii_character, b'x');
+    assert_eq!(writer.screen.chars[2][5].ascii_character, b' ');
+    writer.write_string("\u{1b}[2J");
+    assert_eq!(writer.screen.chars[0][0].ascii_character, b' ');
+}
@@ -1,20 +1,32 @@
 #![no_std] // don't link the Rust standard library
 #![no_main] // disable all Rust-level entry points
+#![feature(custom_test_frameworks)]
+#![test_runner(popcorn::test_runner)]
+#![reexport_test_harness_main = "test_main"]
 use core::panic::PanicInfo;
 /// This function is called on panic.
+#[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    println!("{}", info);
-    hlt_loop();
+    popcorn::low_level::panic_screen::show(info)
+}
+
+#[cfg(test)]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    popcorn::test_panic_handler(info)
 }
 
 use bootloader::{entry_point, BootInfo};
 #[allow(unused_imports)]
 use popcorn::{
     error, hlt_loop, init, log,
-    low_level::vga_buffer::{send_command_to_writer, Color, CommandToWriter},
-    print_with_colors, println,
-    userspace::output::MessageToVga,
+    low_level::{
+        task::{executor::Executor, keyboard, Task},
+        vga_buffer::{send_command_to_writer, Color, CommandToWriter},
+    },
+    println,
+    userspace::{locale::message, shell},
     warn,
 };
 entry_point!(kernel_main);
@@ -22,14 +34,20 @@ entry_point!(kernel_main);
 fn kernel_main(boot_info: &'static BootInfo) -> ! {
     send_command_to_writer(CommandToWriter::ClearScreen(Color::Black));
 
-    print_with_colors!(
-        MessageToVga::new(Color::Yellow, Color::Black, "Welcome to the "),
-        MessageToVga::new(Color::LightBlue, Color::Black, "Popcorn Kernel!")
+    println!(
+        "\x1b[93m{}\x1b[94m{}\x1b[0m",
+        message("boot.welcome"),
+        message("boot.kernel_name")
     );
-    println!(); //Newline being other than black and white caused a bug with the cursor
-    log!("Initializing...");
+    log!(message("boot.initializing"));
     init(boot_info);
-    log!("Initialized!");
+    log!(message("boot.initialized"));
+
+    #[cfg(test)]
+    test_main();
 
-    hlt_loop();
+    shell::init();
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(keyboard::process_keypresses()));
+    executor.run();
 }
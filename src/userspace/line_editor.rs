@@ -0,0 +1,440 @@
+//The line the user is typing. Keys edit the buffer, the screen only ever shows what is in it.
+//Shortcuts follow the usual terminal ones: Ctrl+A/E jump to the start/end, Ctrl+K/U delete
+//up to the end/start and Ctrl+W deletes the word before the cursor. Up/Down go through the
+//history, Ctrl+R searches it backwards and Tab completes the word before the cursor.
+use alloc::{borrow::Cow, format, string::String, vec::Vec};
+
+use crate::{
+    low_level::keyboard::{DecodedKey, KeyCode, KeyEvent, KeyState},
+    userspace::locale::message,
+};
+
+pub use history::History;
+
+mod history;
+
+const BACKSPACE: char = '\u{8}';
+const DELETE: char = '\u{7f}';
+const ESCAPE: char = '\u{1b}';
+
+/// Gets the line up to the cursor and returns the words the last word on it could become.
+pub type Completer = fn(&str) -> Vec<String>;
+
+/// What a key press did, besides editing the line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineEvent {
+    /// Enter was pressed, this is the finished line. The editor is empty again.
+    Submitted(String),
+    /// Tab found more than one completion and couldn't pick.
+    Candidates(Vec<String>),
+}
+
+pub struct LineEditor {
+    line: String,
+    /// In characters, not bytes. Between 0 and the length of the line.
+    cursor: usize,
+    history: History,
+    /// The history entry being shown while going through it with Up/Down.
+    history_position: Option<usize>,
+    /// What was typed before going through the history, brought back by going past the newest entry.
+    draft: String,
+    search: Option<ReverseSearch>,
+    completer: Option<Completer>,
+}
+
+struct ReverseSearch {
+    query: String,
+    /// The history entry the query was last found in.
+    found: Option<usize>,
+    /// The line from before the search, for when it is cancelled.
+    original: String,
+}
+
+impl Default for LineEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LineEditor {
+    pub const fn new() -> Self {
+        LineEditor {
+            line: String::new(),
+            cursor: 0,
+            history: History::new(),
+            history_position: None,
+            draft: String::new(),
+            search: None,
+            completer: None,
+        }
+    }
+
+    pub fn set_completer(&mut self, completer: Completer) {
+        self.completer = Some(completer);
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// What to draw and where the cursor goes in it. Usually that is the line itself, during
+    /// a reverse search it is the query and the entry it was found in.
+    pub fn display(&self) -> (Cow<'_, str>, usize) {
+        match &self.search {
+            None => (Cow::Borrowed(&self.line), self.cursor),
+            Some(search) => {
+                let found = search
+                    .found
+                    .and_then(|index| self.history.get(index))
+                    .unwrap_or("");
+                // the cursor sits at the end of the query, before its closing quote
+                let before_cursor =
+                    format!("{}'{}", message("editor.reverse_search"), search.query);
+                let cursor = before_cursor.chars().count();
+                (Cow::Owned(format!("{}': {}", before_cursor, found)), cursor)
+            }
+        }
+    }
+
+    /// Applies a key press and tells if it finished the line or needs candidates shown.
+    pub fn handle_key(&mut self, event: &KeyEvent) -> Option<LineEvent> {
+        if event.state != KeyState::Down {
+            return None;
+        }
+        let key = event.key?;
+        let ctrl = event.modifiers.ctrl();
+        if self.search.is_some() && self.handle_search_key(key, ctrl) {
+            return None;
+        }
+        match key {
+            DecodedKey::Unicode('\n') => return Some(LineEvent::Submitted(self.submit())),
+            DecodedKey::Unicode('\t') => return self.complete(),
+            DecodedKey::Unicode(character) if ctrl => match character {
+                'a' => self.home(),
+                'e' => self.end(),
+                'k' => self.delete_to_end(),
+                'u' => self.delete_to_start(),
+                'w' => self.delete_word(),
+                'r' => self.start_search(),
+                _ => {}
+            },
+            DecodedKey::Unicode(BACKSPACE) => self.backspace(),
+            DecodedKey::Unicode(DELETE) => self.delete(),
+            DecodedKey::Unicode(character) if !character.is_control() => self.insert(character),
+            DecodedKey::RawKey(KeyCode::Backspace) => self.backspace(),
+            DecodedKey::RawKey(KeyCode::Delete) => self.delete(),
+            DecodedKey::RawKey(KeyCode::ArrowLeft) => self.cursor = self.cursor.saturating_sub(1),
+            DecodedKey::RawKey(KeyCode::ArrowRight) => {
+                self.cursor = (self.cursor + 1).min(self.len())
+            }
+            DecodedKey::RawKey(KeyCode::Home) => self.home(),
+            DecodedKey::RawKey(KeyCode::End) => self.end(),
+            DecodedKey::RawKey(KeyCode::ArrowUp) => self.history_previous(),
+            DecodedKey::RawKey(KeyCode::ArrowDown) => self.history_next(),
+            _ => {}
+        }
+        None
+    }
+
+    /// Shows the entry before the one shown, starting with the newest.
+    pub fn history_previous(&mut self) {
+        let position = match self.history_position {
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = core::mem::take(&mut self.line);
+                self.history.len() - 1
+            }
+            Some(position) => position.saturating_sub(1),
+        };
+        self.show_history_entry(position);
+    }
+
+    /// Shows the entry after the one shown, and what was typed before after the newest.
+    pub fn history_next(&mut self) {
+        let Some(position) = self.history_position else {
+            return;
+        };
+        if position + 1 < self.history.len() {
+            self.show_history_entry(position + 1);
+        } else {
+            self.history_position = None;
+            let draft = core::mem::take(&mut self.draft);
+            self.set_line(&draft);
+        }
+    }
+
+    fn show_history_entry(&mut self, position: usize) {
+        self.history_position = Some(position);
+        self.line.clear();
+        self.line.push_str(self.history.get(position).unwrap_or(""));
+        self.cursor = self.len();
+    }
+
+    fn start_search(&mut self) {
+        self.search = Some(ReverseSearch {
+            query: String::new(),
+            found: None,
+            original: self.line.clone(),
+        });
+    }
+
+    /// Handles a key during a reverse search. Returns false for keys that end the search
+    /// and should then be handled like any other key, on the line that was found.
+    fn handle_search_key(&mut self, key: DecodedKey, ctrl: bool) -> bool {
+        let Some(search) = self.search.as_mut() else {
+            return false;
+        };
+        match key {
+            DecodedKey::Unicode('r') if ctrl => {
+                let before = search.found.unwrap_or(self.history.len());
+                if let Some(found) = self.history.search(&search.query, before) {
+                    search.found = Some(found);
+                }
+            }
+            DecodedKey::Unicode('g') if ctrl => self.cancel_search(),
+            DecodedKey::Unicode(ESCAPE) => self.cancel_search(),
+            DecodedKey::Unicode(BACKSPACE) | DecodedKey::RawKey(KeyCode::Backspace) => {
+                search.query.pop();
+                search.found = self.history.search(&search.query, self.history.len());
+            }
+            DecodedKey::Unicode(character) if !ctrl && !character.is_control() => {
+                search.query.push(character);
+                // keep the older match if the longer query isn't found
+                let newest = self.history.search(&search.query, self.history.len());
+                search.found = newest.or(search.found);
+            }
+            _ => {
+                self.accept_search();
+                return false;
+            }
+        }
+        true
+    }
+
+    fn accept_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            let found = search.found.and_then(|index| self.history.get(index));
+            let line = String::from(found.unwrap_or(&search.original));
+            self.set_line(&line);
+        }
+    }
+
+    fn cancel_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.set_line(&search.original);
+        }
+    }
+
+    /// Completes the word before the cursor as far as all candidates agree. Returns the
+    /// candidates if that didn't add anything.
+    fn complete(&mut self) -> Option<LineEvent> {
+        let completer = self.completer?;
+        let before_cursor = &self.line[..self.byte_index(self.cursor)];
+        let word_start = before_cursor
+            .trim_end_matches(|character: char| !character.is_whitespace())
+            .len();
+        let word = &before_cursor[word_start..];
+        let candidates = completer(before_cursor);
+        let common = common_prefix(&candidates)?;
+        let completion = match candidates.len() {
+            1 => format!("{} ", common),
+            _ if common.len() > word.len() => String::from(common),
+            _ => return Some(LineEvent::Candidates(candidates)),
+        };
+        let added = completion.strip_prefix(word).unwrap_or(&completion);
+        for character in added.chars() {
+            self.insert(character);
+        }
+        None
+    }
+
+    pub fn insert(&mut self, character: char) {
+        let index = self.byte_index(self.cursor);
+        self.line.insert(index, character);
+        self.cursor += 1;
+    }
+
+    /// Removes the character before the cursor.
+    pub fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.delete();
+        }
+    }
+
+    /// Removes the character under the cursor.
+    pub fn delete(&mut self) {
+        if self.cursor < self.len() {
+            self.line.remove(self.byte_index(self.cursor));
+        }
+    }
+
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = self.len();
+    }
+
+    pub fn delete_to_end(&mut self) {
+        self.line.truncate(self.byte_index(self.cursor));
+    }
+
+    pub fn delete_to_start(&mut self) {
+        self.line.replace_range(..self.byte_index(self.cursor), "");
+        self.cursor = 0;
+    }
+
+    /// Removes the spaces before the cursor and the word before them.
+    pub fn delete_word(&mut self) {
+        let before: Vec<char> = self.line.chars().take(self.cursor).collect();
+        let spaces = before
+            .iter()
+            .rev()
+            .take_while(|c| c.is_whitespace())
+            .count();
+        let word = before[..before.len() - spaces]
+            .iter()
+            .rev()
+            .take_while(|c| !c.is_whitespace())
+            .count();
+        let start = self.cursor - spaces - word;
+        let range = self.byte_index(start)..self.byte_index(self.cursor);
+        self.line.replace_range(range, "");
+        self.cursor = start;
+    }
+
+    /// Replaces the line, with the cursor at its end.
+    pub fn set_line(&mut self, line: &str) {
+        self.line.clear();
+        self.line.push_str(line);
+        self.cursor = self.len();
+    }
+
+    fn submit(&mut self) -> String {
+        self.cursor = 0;
+        self.history_position = None;
+        self.draft.clear();
+        let line = core::mem::take(&mut self.line);
+        self.history.push(&line);
+        line
+    }
+
+    fn len(&self) -> usize {
+        self.line.chars().count()
+    }
+
+    fn byte_index(&self, cursor: usize) -> usize {
+        self.line
+            .char_indices()
+            .nth(cursor)
+            .map_or(self.line.len(), |(index, _)| index)
+    }
+}
+
+/// The longest start all of `words` share, `None` if there are none.
+fn common_prefix(words: &[String]) -> Option<&str> {
+    let (first, rest) = words.split_first()?;
+    let length = rest.iter().fold(first.len(), |length, word| {
+        first[..length]
+            .char_indices()
+            .zip(word.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(length.min(word.len()), |((index, _), _)| index)
+    });
+    Some(&first[..length])
+}
+
+#[test_case]
+fn edits_at_the_cursor() {
+    let mut editor = LineEditor::new();
+    for character in "hllo".chars() {
+        editor.insert(character);
+    }
+    editor.home();
+    editor.cursor += 1;
+    editor.insert('e');
+    editor.end();
+    editor.backspace();
+    assert_eq!((editor.line(), editor.cursor()), ("hell", 4));
+    editor.cursor = 1;
+    editor.delete_to_end();
+    assert_eq!(editor.line(), "h");
+}
+
+#[test_case]
+fn deletes_words_and_multibyte_characters() {
+    let mut editor = LineEditor::new();
+    editor.set_line("echo  año ");
+    editor.delete_word();
+    assert_eq!(editor.line(), "echo  ");
+    editor.set_line("año");
+    editor.cursor = 2;
+    editor.delete_to_start();
+    assert_eq!((editor.line(), editor.cursor()), ("o", 0));
+}
+
+#[test_case]
+fn browses_searches_and_completes() {
+    use crate::low_level::keyboard::Modifiers;
+
+    fn completer(before_cursor: &str) -> Vec<String> {
+        ["help", "halt", "history"]
+            .into_iter()
+            .filter(|name| name.starts_with(before_cursor))
+            .map(String::from)
+            .collect()
+    }
+    let press = |key: DecodedKey, ctrl: bool| KeyEvent {
+        code: KeyCode::Escape,
+        state: KeyState::Down,
+        key: Some(key),
+        modifiers: Modifiers {
+            left_ctrl: ctrl,
+            ..Modifiers::new()
+        },
+    };
+
+    let mut editor = LineEditor::new();
+    editor.set_completer(completer);
+    for line in ["echo one", "mem", "echo two"] {
+        editor.set_line(line);
+        editor.submit();
+    }
+    editor.set_line("dra");
+    editor.history_previous();
+    editor.history_previous();
+    assert_eq!(editor.line(), "mem");
+    editor.history_next();
+    editor.history_next();
+    assert_eq!(editor.line(), "dra");
+
+    editor.set_line("");
+    for key in [DecodedKey::Unicode('e'), DecodedKey::Unicode('c')] {
+        editor.handle_key(&press(key, false));
+    }
+    editor.handle_key(&press(DecodedKey::Unicode('r'), true));
+    assert_eq!(editor.line(), "ec");
+    editor.handle_key(&press(DecodedKey::Unicode('o'), false));
+    editor.handle_key(&press(DecodedKey::Unicode('r'), true));
+    let label = message("editor.reverse_search");
+    assert_eq!(editor.display().0, format!("{}'o': echo one", label));
+    editor.handle_key(&press(DecodedKey::RawKey(KeyCode::End), false));
+    assert_eq!(editor.line(), "echo one");
+
+    editor.set_line("h");
+    let event = editor.handle_key(&press(DecodedKey::Unicode('\t'), false));
+    assert!(matches!(event, Some(LineEvent::Candidates(candidates)) if candidates.len() == 3));
+    editor.set_line("he");
+    editor.handle_key(&press(DecodedKey::Unicode('\t'), false));
+    assert_eq!(editor.line(), "help ");
+}
@@ -0,0 +1,67 @@
+//The lines entered so far, oldest first. Once full, every new line pushes out the oldest one.
+use alloc::{collections::VecDeque, string::String};
+
+pub const HISTORY_CAPACITY: usize = 64;
+
+pub struct History {
+    entries: VecDeque<String>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl History {
+    pub const fn new() -> Self {
+        History {
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// Remembers `line`, unless it is empty or the same as the newest entry.
+    pub fn push(&mut self, line: &str) {
+        if line.trim().is_empty() || self.entries.back().is_some_and(|last| last == line) {
+            return;
+        }
+        if self.entries.len() == HISTORY_CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(String::from(line));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The entry at `index`, 0 being the oldest.
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(String::as_str)
+    }
+
+    /// The index of the newest entry older than `before` that contains `query`.
+    pub fn search(&self, query: &str, before: usize) -> Option<usize> {
+        self.entries
+            .iter()
+            .take(before)
+            .rposition(|entry| entry.contains(query))
+    }
+}
+
+#[test_case]
+fn drops_the_oldest_entries_when_full() {
+    let mut history = History::new();
+    for number in 0..HISTORY_CAPACITY + 2 {
+        history.push(&alloc::format!("line {}", number));
+    }
+    history.push("");
+    history.push("line 65");
+    assert_eq!(history.len(), HISTORY_CAPACITY);
+    assert_eq!(history.get(0), Some("line 2"));
+    assert_eq!(history.search("line 1", history.len()), Some(17));
+}
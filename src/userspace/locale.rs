@@ -0,0 +1,123 @@
+//Localized messages. Every language has two files under locale/: <code>_messages.txt with
+//"key = value" lines, and <code>_panic.txt with the panic screen template.
+//The language is picked at build time with the locale-* cargo features, and can be changed at runtime.
+use core::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Language {
+    English,
+    Spanish,
+    German,
+}
+
+pub const LANGUAGES: &[Language] = &[Language::English, Language::Spanish, Language::German];
+
+const DEFAULT_LANGUAGE: Language = if cfg!(feature = "locale-es") {
+    Language::Spanish
+} else if cfg!(feature = "locale-de") {
+    Language::German
+} else {
+    Language::English
+};
+
+static LANGUAGE: AtomicU8 = AtomicU8::new(DEFAULT_LANGUAGE as u8);
+
+impl Language {
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::Spanish => "es",
+            Language::German => "de",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        LANGUAGES
+            .iter()
+            .copied()
+            .find(|language| language.code() == code)
+    }
+
+    fn messages(self) -> &'static str {
+        match self {
+            Language::English => include_str!("../../locale/en_messages.txt"),
+            Language::Spanish => include_str!("../../locale/es_messages.txt"),
+            Language::German => include_str!("../../locale/de_messages.txt"),
+        }
+    }
+
+    fn panic_template(self) -> &'static str {
+        match self {
+            Language::English => include_str!("../../locale/en_panic.txt"),
+            Language::Spanish => include_str!("../../locale/es_panic.txt"),
+            Language::German => include_str!("../../locale/de_panic.txt"),
+        }
+    }
+}
+
+pub fn language() -> Language {
+    LANGUAGES[LANGUAGE.load(Ordering::Relaxed) as usize]
+}
+
+pub fn set_language(language: Language) {
+    LANGUAGE.store(language as u8, Ordering::Relaxed);
+}
+
+/// Looks `key` up in the current language, then in English. Returns the key itself
+/// if neither catalog has it, so a missing translation is visible but harmless.
+pub fn message(key: &'static str) -> &'static str {
+    find_message(language().messages(), key)
+        .or_else(|| find_message(Language::English.messages(), key))
+        .unwrap_or(key)
+}
+
+pub fn panic_template() -> &'static str {
+    language().panic_template()
+}
+
+fn find_message(catalog: &'static str, key: &str) -> Option<&'static str> {
+    catalog
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .find(|(line_key, _)| line_key.trim() == key)
+        .map(|(_, value)| {
+            let value = value.trim();
+            value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(value)
+        })
+}
+
+#[test_case]
+fn quoted_values_keep_their_spaces() {
+    assert_eq!(find_message("a = 1\nb = \" two \"\n", "b"), Some(" two "));
+    assert_eq!(find_message("# b = 1\n", "b"), None);
+}
+
+#[test_case]
+fn missing_keys_fall_back_to_english() {
+    set_language(Language::German);
+    assert_eq!(message("log.info"), "");
+    assert_eq!(message("no.such.key"), "no.such.key");
+    set_language(DEFAULT_LANGUAGE);
+}
+
+#[test_case]
+fn catalogs_only_use_characters_the_vga_font_has() {
+    use crate::low_level::vga_buffer::buffer::to_code_page_437;
+
+    for language in LANGUAGES {
+        for catalog in [language.messages(), language.panic_template()] {
+            if let Some(character) = catalog
+                .chars()
+                .find(|&character| to_code_page_437(character) == 0xFE)
+            {
+                panic!("{:?} has {:?}, which VGA can't show", language, character);
+            }
+        }
+    }
+}
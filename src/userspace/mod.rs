@@ -1,2 +1,5 @@
+pub mod line_editor;
+pub mod locale;
 pub mod output;
+pub mod shell;
 pub mod user_interface;
@@ -1,16 +1,8 @@
-use crate::low_level::vga_buffer::{send_command_to_writer, Color, CommandToWriter};
+use core::fmt;
 
-#[macro_export]
-macro_rules! print_with_colors {
-    ( $( $x:expr ),* ) => {
-        {
-            $(
-                $x.print_to_vga();
-            )*
-        }
-        $crate::low_level::vga_buffer::send_command_to_writer(popcorn::low_level::vga_buffer::CommandToWriter::SetColor(Color::White, Color::Black));
-    }
-}
+use crate::{low_level::serial, userspace::locale::message};
+
+/// Prints to the screen. ANSI escape sequences like `\x1b[31m` change the colors.
 #[macro_export]
 macro_rules! print {
     ($($arg:tt)*) => ($crate::low_level::vga_buffer::send_command_to_writer($crate::low_level::vga_buffer::CommandToWriter::Print(format_args!($($arg)*))));
@@ -22,17 +14,24 @@ macro_rules! println {
     ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
 }
 
+#[macro_export]
+macro_rules! serial_print {
+    ($($arg:tt)*) => ($crate::low_level::serial::print(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! serial_println {
+    () => ($crate::serial_print!("\n"));
+    ($($arg:tt)*) => ($crate::serial_print!("{}\n", format_args!($($arg)*)));
+}
+
 /// log!("text") - this will get the file that called this, and say it as [file_name] <text>
+/// Like `warn!` and `error!`, it is also copied to the serial port unless
+/// `serial::set_log_mirroring(false)` was called.
 #[macro_export]
 macro_rules! log {
     ($($arg:tt)*) => (
-        $crate::print_with_colors!(
-            $crate::userspace::output::MessageToVga::new($crate::low_level::vga_buffer::Color::LightBlue, $crate::low_level::vga_buffer::Color::Black, "["),
-            $crate::userspace::output::MessageToVga::new($crate::low_level::vga_buffer::Color::Black, $crate::low_level::vga_buffer::Color::LightGreen, core::file!()),
-            $crate::userspace::output::MessageToVga::new($crate::low_level::vga_buffer::Color::LightBlue, $crate::low_level::vga_buffer::Color::Black, "] "),
-            $crate::userspace::output::MessageToVga::new($crate::low_level::vga_buffer::Color::White, $crate::low_level::vga_buffer::Color::Black, $($arg)*)
-        );
-        $crate::println!();
+        $crate::userspace::output::print_log($crate::userspace::output::LogLevel::Info, core::file!(), $($arg)*)
     )
 }
 
@@ -40,13 +39,7 @@ macro_rules! log {
 #[macro_export]
 macro_rules! warn {
     ($($arg:tt)*) => (
-        $crate::print_with_colors!(
-            $crate::userspace::output::MessageToVga::new($crate::low_level::vga_buffer::Color::LightBlue, $crate::low_level::vga_buffer::Color::Black, "["),
-            $crate::userspace::output::MessageToVga::new($crate::low_level::vga_buffer::Color::Black, $crate::low_level::vga_buffer::Color::Yellow, core::file!()),
-            $crate::userspace::output::MessageToVga::new($crate::low_level::vga_buffer::Color::LightBlue, $crate::low_level::vga_buffer::Color::Black, "] "),
-            $crate::userspace::output::MessageToVga::new($crate::low_level::vga_buffer::Color::Yellow, $crate::low_level::vga_buffer::Color::Black, $($arg)*)
-        );
-        $crate::println!();
+        $crate::userspace::output::print_log($crate::userspace::output::LogLevel::Warning, core::file!(), $($arg)*)
     )
 }
 
@@ -54,32 +47,66 @@ macro_rules! warn {
 #[macro_export]
 macro_rules! error {
     ($($arg:tt)*) => (
-        $crate::print_with_colors!(
-            $crate::userspace::output::MessageToVga::new($crate::low_level::vga_buffer::Color::LightBlue, $crate::low_level::vga_buffer::Color::Black, "["),
-            $crate::userspace::output::MessageToVga::new($crate::low_level::vga_buffer::Color::Black, $crate::low_level::vga_buffer::Color::LightRed, core::file!()),
-            $crate::userspace::output::MessageToVga::new($crate::low_level::vga_buffer::Color::LightBlue, $crate::low_level::vga_buffer::Color::Black, "] "),
-            $crate::userspace::output::MessageToVga::new($crate::low_level::vga_buffer::Color::LightRed, $crate::low_level::vga_buffer::Color::Black, $($arg)*)
-        );
-        $crate::println!();
+        $crate::userspace::output::print_log($crate::userspace::output::LogLevel::Error, core::file!(), $($arg)*)
     )
 }
 
-pub struct MessageToVga<'a> {
-    foreground: Color,
-    background: Color,
-    string: &'a str,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Info,
+    Warning,
+    Error,
 }
 
-impl<'a> MessageToVga<'a> {
-    pub fn print_to_vga(&self) {
-        send_command_to_writer(CommandToWriter::SetColor(self.foreground, self.background));
-        print!("{}", self.string);
+impl LogLevel {
+    /// The SGR parameters for the file name and for the message.
+    fn colors(self) -> (&'static str, &'static str) {
+        match self {
+            LogLevel::Info => ("30;102", "97"),
+            LogLevel::Warning => ("30;103", "93"),
+            LogLevel::Error => ("30;101", "91"),
+        }
     }
-    pub fn new(foreground: Color, background: Color, string: &'a str) -> Self {
-        MessageToVga {
-            foreground,
-            background,
-            string,
+
+    fn prefix(self) -> &'static str {
+        match self {
+            LogLevel::Info => message("log.info"),
+            LogLevel::Warning => message("log.warning"),
+            LogLevel::Error => message("log.error"),
         }
     }
 }
+
+/// A line of `log!`, `warn!` or `error!`. The colors are ANSI escape sequences, so the
+/// screen and the serial port get the same text.
+struct LogLine<'a> {
+    level: LogLevel,
+    file: &'a str,
+    message: &'a str,
+}
+
+impl fmt::Display for LogLine<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (file_color, message_color) = self.level.colors();
+        writeln!(
+            f,
+            "\x1b[94m[\x1b[{}m{}\x1b[0;94m] \x1b[{}m{}{}\x1b[0m",
+            file_color,
+            self.file,
+            message_color,
+            self.level.prefix(),
+            self.message
+        )
+    }
+}
+
+/// Used by `log!`, `warn!` and `error!`.
+pub fn print_log(level: LogLevel, file: &str, message: &str) {
+    let line = LogLine {
+        level,
+        file,
+        message,
+    };
+    print!("{}", line);
+    serial::mirror_log(format_args!("{}", line));
+}
@@ -0,0 +1,235 @@
+//The kernel shell. Gets the lines the user finishes from user_interface, splits them into
+//arguments and runs the command named by the first one. Commands are looked up in a registry:
+//init() fills it with the built-ins from `builtins`, register() adds more.
+//Tab completes command names, and arguments for commands that have a completion hook.
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::fmt;
+
+use crate::{
+    low_level::{
+        sync::{IrqSafeMutex, RwLock},
+        vga_buffer::{send_command_to_writer, Color, CommandToWriter},
+    },
+    println,
+    userspace::{locale::message, user_interface},
+};
+
+mod builtins;
+
+const PROMPT: &str = "popcorn> ";
+
+/// Replaced as a whole by `register`. Readers only clone the `Arc`, so nothing is allocated or
+/// freed while the lock keeps interrupts off.
+static COMMANDS: RwLock<Option<Arc<Vec<Command>>>> = RwLock::new(None);
+/// Foreground and background of normal output, changed by the `color` command.
+static TEXT_COLORS: IrqSafeMutex<(Color, Color)> = IrqSafeMutex::new((Color::White, Color::Black));
+
+#[derive(Debug, Clone, Copy)]
+pub struct Command {
+    pub name: &'static str,
+    /// The arguments it takes, as `help` shows them.
+    pub usage: &'static str,
+    /// Locale key of the description `help` shows.
+    pub description: &'static str,
+    pub run: fn(&[&str]) -> Result<(), CommandError>,
+    pub complete: Option<CompletionHook>,
+}
+
+/// Gets the arguments before the one being completed and returns what that one could be.
+pub type CompletionHook = fn(&[&str]) -> Vec<&'static str>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    /// Too many or too few arguments. The usage is shown.
+    WrongArguments,
+    InvalidArgument(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    UnterminatedQuote,
+}
+
+/// Registers the built-in commands, starts receiving lines and shows the first prompt.
+pub fn init() {
+    for command in builtins::BUILTINS {
+        register(*command);
+    }
+    user_interface::set_line_handler(run_line);
+    user_interface::set_completer(complete);
+    user_interface::set_prompt(PROMPT);
+    user_interface::show_prompt();
+}
+
+/// Adds `command`, replacing a registered command with the same name.
+pub fn register(command: Command) {
+    loop {
+        let current = registered();
+        let mut commands = current.as_deref().cloned().unwrap_or_default();
+        commands.retain(|registered| registered.name != command.name);
+        commands.push(command);
+        let commands = Some(Arc::new(commands));
+
+        let mut registry = COMMANDS.write();
+        let unchanged = match (&*registry, &current) {
+            (Some(registry), Some(current)) => Arc::ptr_eq(registry, current),
+            (registry, current) => registry.is_none() && current.is_none(),
+        };
+        // `current` still holds the old list, so replacing it frees nothing
+        if unchanged {
+            *registry = commands;
+            return;
+        }
+        // someone else registered a command in between, start over from their list
+    }
+}
+
+pub fn find(name: &str) -> Option<Command> {
+    registered()?
+        .iter()
+        .find(|command| command.name == name)
+        .copied()
+}
+
+/// Every registered command, in the order they were registered.
+pub fn commands() -> Vec<Command> {
+    registered().map_or_else(Vec::new, |commands| commands.to_vec())
+}
+
+fn registered() -> Option<Arc<Vec<Command>>> {
+    COMMANDS.read().clone()
+}
+
+/// Runs the command on `line`.
+pub fn run_line(line: &str) {
+    match parse_arguments(line) {
+        Ok(arguments) => {
+            if let Some((name, arguments)) = arguments.split_first() {
+                run(name, arguments);
+            }
+        }
+        Err(ParseError::UnterminatedQuote) => {
+            print_error(format_args!("{}", message("shell.unterminated_quote")))
+        }
+    }
+    // the prompt comes next, in the normal colors even if something else changed them
+    let (foreground, background) = *TEXT_COLORS.lock();
+    send_command_to_writer(CommandToWriter::SetColor(foreground, background));
+}
+
+/// The words the last word of `before_cursor` could be completed to: command names for
+/// the first word, whatever the command's completion hook offers for the others.
+pub fn complete(before_cursor: &str) -> Vec<String> {
+    let word_start = before_cursor
+        .trim_end_matches(|character: char| !character.is_whitespace())
+        .len();
+    let (before_word, word) = before_cursor.split_at(word_start);
+    let arguments: Vec<&str> = before_word.split_whitespace().collect();
+    let candidates = match arguments.split_first() {
+        None => commands().iter().map(|command| command.name).collect(),
+        Some((name, arguments)) => find(name)
+            .and_then(|command| command.complete)
+            .map_or_else(Vec::new, |complete| complete(arguments)),
+    };
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(word))
+        .map(String::from)
+        .collect()
+}
+
+fn run(name: &str, arguments: &[&str]) {
+    let Some(command) = find(name) else {
+        print_error(format_args!("{}{}", message("shell.unknown_command"), name));
+        return;
+    };
+    match (command.run)(arguments) {
+        Ok(()) => {}
+        Err(CommandError::WrongArguments) => print_error(format_args!(
+            "{}{} {}",
+            message("shell.usage"),
+            command.name,
+            command.usage
+        )),
+        Err(CommandError::InvalidArgument(argument)) => print_error(format_args!(
+            "{}{}",
+            message("shell.invalid_argument"),
+            argument
+        )),
+    }
+}
+
+/// Splits `line` at whitespace. Double quotes keep what is between them together, so
+/// `echo "a  b"` gets `a  b` as one argument.
+pub fn parse_arguments(line: &str) -> Result<Vec<&str>, ParseError> {
+    let mut arguments = Vec::new();
+    let mut rest = line.trim_start();
+    while !rest.is_empty() {
+        let (argument, remaining) = match rest.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').ok_or(ParseError::UnterminatedQuote)?;
+                (&quoted[..end], &quoted[end + 1..])
+            }
+            None => rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len())),
+        };
+        arguments.push(argument);
+        rest = remaining.trim_start();
+    }
+    Ok(arguments)
+}
+
+fn set_text_colors(foreground: Color, background: Color) {
+    *TEXT_COLORS.lock() = (foreground, background);
+    send_command_to_writer(CommandToWriter::SetColor(foreground, background));
+}
+
+fn print_error(error: fmt::Arguments) {
+    println!("\x1b[91;40m{}\x1b[0m", error);
+}
+
+#[test_case]
+fn quotes_group_arguments() {
+    assert_eq!(
+        parse_arguments("  echo \"a  b\" c "),
+        Ok(alloc::vec!["echo", "a  b", "c"])
+    );
+    assert_eq!(parse_arguments(""), Ok(Vec::new()));
+    assert_eq!(
+        parse_arguments("echo \"a"),
+        Err(ParseError::UnterminatedQuote)
+    );
+}
+
+#[test_case]
+fn registered_commands_replace_older_ones() {
+    fn fails(_: &[&str]) -> Result<(), CommandError> {
+        Err(CommandError::WrongArguments)
+    }
+    let command = Command {
+        name: "test-command",
+        usage: "",
+        description: "",
+        run: fails,
+        complete: None,
+    };
+    register(command);
+    register(Command {
+        usage: "<argument>",
+        ..command
+    });
+    let found = find("test-command").unwrap();
+    assert_eq!(found.usage, "<argument>");
+    assert_eq!((found.run)(&[]), Err(CommandError::WrongArguments));
+    assert_eq!(
+        commands()
+            .iter()
+            .filter(|command| command.name == "test-command")
+            .count(),
+        1
+    );
+    assert_eq!(
+        complete("test-c"),
+        alloc::vec![String::from("test-command")]
+    );
+    assert!(complete("test-command ").is_empty());
+}
@@ -0,0 +1,227 @@
+//The commands the shell starts with.
+use alloc::{string::ToString, vec::Vec};
+
+use super::{set_text_colors, Command, CommandError};
+use crate::{
+    low_level::{
+        allocator, apic,
+        interrupts::{self, InterruptIndex},
+        keyboard::{self, Layout, LAYOUTS},
+        memory::MEMORY,
+        power, timer,
+        vga_buffer::{send_command_to_writer, Color, CommandToWriter},
+    },
+    println,
+    userspace::locale::message,
+};
+
+pub(super) const BUILTINS: &[Command] = &[
+    Command {
+        name: "help",
+        usage: "",
+        description: "command.help",
+        run: help,
+        complete: None,
+    },
+    Command {
+        name: "clear",
+        usage: "",
+        description: "command.clear",
+        run: clear,
+        complete: None,
+    },
+    Command {
+        name: "echo",
+        usage: "[text...]",
+        description: "command.echo",
+        run: echo,
+        complete: None,
+    },
+    Command {
+        name: "mem",
+        usage: "",
+        description: "command.mem",
+        run: mem,
+        complete: None,
+    },
+    Command {
+        name: "uptime",
+        usage: "",
+        description: "command.uptime",
+        run: uptime,
+        complete: None,
+    },
+    Command {
+        name: "color",
+        usage: "<fg> <bg>",
+        description: "command.color",
+        run: color,
+        complete: Some(complete_colors),
+    },
+    Command {
+        name: "layout",
+        usage: "[name]",
+        description: "command.layout",
+        run: layout,
+        complete: Some(complete_layouts),
+    },
+    Command {
+        name: "irq",
+        usage: "",
+        description: "command.irq",
+        run: irq,
+        complete: None,
+    },
+    Command {
+        name: "panic",
+        usage: "[message...]",
+        description: "command.panic",
+        run: panic,
+        complete: None,
+    },
+    Command {
+        name: "reboot",
+        usage: "",
+        description: "command.reboot",
+        run: reboot,
+        complete: None,
+    },
+    Command {
+        name: "shutdown",
+        usage: "",
+        description: "command.shutdown",
+        run: shutdown,
+        complete: None,
+    },
+];
+
+fn no_arguments(arguments: &[&str]) -> Result<(), CommandError> {
+    match arguments {
+        [] => Ok(()),
+        _ => Err(CommandError::WrongArguments),
+    }
+}
+
+fn help(arguments: &[&str]) -> Result<(), CommandError> {
+    no_arguments(arguments)?;
+    for command in super::commands() {
+        println!(
+            "{:<9}{:<13}{}",
+            command.name,
+            command.usage,
+            message(command.description)
+        );
+    }
+    Ok(())
+}
+
+fn clear(arguments: &[&str]) -> Result<(), CommandError> {
+    no_arguments(arguments)?;
+    send_command_to_writer(CommandToWriter::ClearScreen(Color::Black));
+    Ok(())
+}
+
+fn echo(arguments: &[&str]) -> Result<(), CommandError> {
+    println!("{}", arguments.join(" "));
+    Ok(())
+}
+
+fn mem(arguments: &[&str]) -> Result<(), CommandError> {
+    no_arguments(arguments)?;
+    let frames = MEMORY.lock().as_ref().map(|memory| {
+        let frames = &memory.frame_allocator;
+        (frames.used_frames(), frames.total_frames())
+    });
+    if let Some((used, total)) = frames {
+        println!("{}{} / {}", message("mem.frames"), used, total);
+    }
+    println!("{}{}", message("mem.heap"), allocator::heap_stats());
+    Ok(())
+}
+
+fn uptime(arguments: &[&str]) -> Result<(), CommandError> {
+    no_arguments(arguments)?;
+    let seconds = timer::uptime().as_secs();
+    println!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    );
+    Ok(())
+}
+
+fn color(arguments: &[&str]) -> Result<(), CommandError> {
+    let [foreground, background] = arguments else {
+        return Err(CommandError::WrongArguments);
+    };
+    let parse = |name: &str| {
+        Color::from_name(name).ok_or_else(|| CommandError::InvalidArgument(name.to_string()))
+    };
+    set_text_colors(parse(foreground)?, parse(background)?);
+    Ok(())
+}
+
+fn complete_colors(arguments: &[&str]) -> Vec<&'static str> {
+    match arguments.len() {
+        0 | 1 => Color::ALL.iter().map(|color| color.name()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn layout(arguments: &[&str]) -> Result<(), CommandError> {
+    match arguments {
+        [] => {
+            let names: Vec<&str> = LAYOUTS.iter().map(|layout| layout.name()).collect();
+            println!("{} ({})", keyboard::layout().name(), names.join(", "));
+        }
+        [name] => {
+            let layout = Layout::from_name(name)
+                .ok_or_else(|| CommandError::InvalidArgument(name.to_string()))?;
+            keyboard::set_layout(layout);
+        }
+        _ => return Err(CommandError::WrongArguments),
+    }
+    Ok(())
+}
+
+fn complete_layouts(arguments: &[&str]) -> Vec<&'static str> {
+    match arguments {
+        [] => LAYOUTS.iter().map(|layout| layout.name()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn irq(arguments: &[&str]) -> Result<(), CommandError> {
+    no_arguments(arguments)?;
+    for index in InterruptIndex::ALL {
+        println!(
+            "{:?}: {}",
+            index,
+            interrupts::interrupt_count(index.as_u8())
+        );
+    }
+    println!(
+        "{}{}",
+        message("irq.spurious"),
+        interrupts::interrupt_count(apic::SPURIOUS_VECTOR)
+    );
+    Ok(())
+}
+
+fn panic(arguments: &[&str]) -> Result<(), CommandError> {
+    match arguments {
+        [] => panic!("{}", message("command.panic_default")),
+        _ => panic!("{}", arguments.join(" ")),
+    }
+}
+
+fn reboot(arguments: &[&str]) -> Result<(), CommandError> {
+    no_arguments(arguments)?;
+    power::reboot()
+}
+
+fn shutdown(arguments: &[&str]) -> Result<(), CommandError> {
+    no_arguments(arguments)?;
+    power::shutdown()
+}
@@ -1,26 +1,98 @@
 //here goes proccessing the input from the user
-//Backspace is implemented twice because even though it has a rawkey, its registered as Unicode.
-//If in some case it would be a raw key, it would cause bugs
+//Typed keys go to the LineEditor, and the screen is redrawn from its buffer after every key.
+//Finished lines are handed to the handler set with set_line_handler(), then the prompt is shown again.
+//Shift+PageUp/PageDown/Home/End browse the scrollback, any other key goes back to the live screen.
+//F10 shuts the machine down and F12 reboots it.
+use alloc::borrow::Cow;
+
 use crate::{
-    low_level::vga_buffer::{send_command_to_writer, CommandToWriter},
+    low_level::{
+        keyboard::{self, DecodedKey, KeyCode, KeyEvent, KeyState},
+        power,
+        sync::IrqSafeMutex,
+        vga_buffer::{send_command_to_writer, CommandToWriter, ScrollView},
+    },
     print,
+    userspace::line_editor::{Completer, LineEditor, LineEvent},
 };
 
-pub fn handle_keypress(key: char) {
-    match key {
-        '\u{8}' => send_command_to_writer(CommandToWriter::Backspace),
-        _ => print!("{}", key),
+static EDITOR: IrqSafeMutex<LineEditor> = IrqSafeMutex::new(LineEditor::new());
+static LINE_HANDLER: IrqSafeMutex<Option<fn(&str)>> = IrqSafeMutex::new(None);
+static PROMPT: IrqSafeMutex<&str> = IrqSafeMutex::new("");
+
+/// Sets what gets the lines the user finished with Enter. Without a handler they are dropped.
+pub fn set_line_handler(handler: fn(&str)) {
+    *LINE_HANDLER.lock() = Some(handler);
+}
+
+/// Sets what Tab asks for the possible completions.
+pub fn set_completer(completer: Completer) {
+    EDITOR.lock().set_completer(completer);
+}
+
+pub fn set_prompt(prompt: &'static str) {
+    *PROMPT.lock() = prompt;
+}
+
+pub fn show_prompt() {
+    let prompt = *PROMPT.lock();
+    print!("{}", prompt);
+}
+
+pub fn handle_key_event(event: KeyEvent) {
+    if event.state != KeyState::Down {
+        return;
+    }
+    match event.key {
+        Some(DecodedKey::RawKey(KeyCode::F10)) => power::shutdown(),
+        Some(DecodedKey::RawKey(KeyCode::F12)) => power::reboot(),
+        _ => {}
+    }
+    if let Some(scroll) = scroll_view(&event) {
+        send_command_to_writer(CommandToWriter::ScrollView(scroll));
+        return;
+    }
+    if !keyboard::is_modifier(event.code) {
+        send_command_to_writer(CommandToWriter::ScrollView(ScrollView::Live));
+    }
+
+    // editing allocates, so the editor is taken out and edited without its lock, which would
+    // keep interrupts off all along
+    let mut editor = core::mem::take(&mut *EDITOR.lock());
+    let line_event = editor.handle_key(&event);
+    if let Some(LineEvent::Candidates(candidates)) = &line_event {
+        print!("\n{}\n", candidates.join("  "));
+        show_prompt();
+    }
+    // a finished line stays on the screen as it was entered, not as the now empty editor
+    let (text, cursor) = match &line_event {
+        Some(LineEvent::Submitted(line)) => (Cow::Borrowed(line.as_str()), line.chars().count()),
+        _ => editor.display(),
+    };
+    send_command_to_writer(CommandToWriter::ShowInput(&text, cursor));
+    drop(text);
+    // this drops the empty editor that stood in, which owns no memory
+    *EDITOR.lock() = editor;
+
+    if let Some(LineEvent::Submitted(line)) = line_event {
+        print!("\n");
+        let handler = *LINE_HANDLER.lock();
+        if let Some(handler) = handler {
+            handler(&line);
+        }
+        show_prompt();
     }
 }
-use pc_keyboard::KeyCode;
-pub fn handle_raw_keypress(key: KeyCode) {
-    match key {
-        KeyCode::Backspace => send_command_to_writer(CommandToWriter::Backspace),
-        KeyCode::LShift => {}
-        KeyCode::RShift => {}
-        KeyCode::CapsLock => {}
-        KeyCode::ArrowLeft => send_command_to_writer(CommandToWriter::CursorBack),
-        KeyCode::ArrowRight => send_command_to_writer(CommandToWriter::CursorFront),
-        _ => print!("{:?}", key),
+
+fn scroll_view(event: &KeyEvent) -> Option<ScrollView> {
+    if !event.modifiers.shift() {
+        return None;
+    }
+    match event.key? {
+        DecodedKey::RawKey(KeyCode::PageUp) => Some(ScrollView::PageUp),
+        DecodedKey::RawKey(KeyCode::PageDown) => Some(ScrollView::PageDown),
+        DecodedKey::RawKey(KeyCode::Home) => Some(ScrollView::Oldest),
+        DecodedKey::RawKey(KeyCode::End) => Some(ScrollView::Live),
+        _ => None,
     }
 }